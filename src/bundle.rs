@@ -0,0 +1,188 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{CommandError, Flavor, InstallError, Operation, Options, Parser, Root, apply_lockfile,
+            lockfile, url};
+use cache;
+use git;
+
+#[derive(Debug)]
+/// The contents of a bundle extracted by `extract_bundle`.
+pub struct Bundle {
+    /// Content of the flavor file.
+    pub flavor_file: String,
+    /// Content of the lockfile, which locks each flavor to the commit in the bundle.
+    pub lockfile: String,
+    /// The flavors in the flavor file, which are locked by the lockfile.
+    pub flavors: Vec<Flavor>,
+    /// The directory of bare mirrors of the plugins, which can be used as the cache of
+    /// `install` in offline mode.
+    pub cache: PathBuf,
+}
+
+const FLAVOR_FILE: &str = "VimFlavor";
+const LOCKFILE: &str = "VimFlavor.lock";
+const CACHE: &str = "cache";
+
+/// Writes a tar archive `out` which contains `flavor_file`, the content of the flavor file,
+/// a lockfile of `fs` installed under `root`, and bare mirrors of their repositories at the
+/// checked-out commits. Every flavor must be installed.
+///
+/// In the archive, the mirrors are named after the repositories in the flavor file rather than
+/// their URLs, which may be rewritten differently where the bundle is extracted.
+pub fn export_bundle(
+    flavor_file: &str,
+    fs: &[Flavor],
+    root: &Root,
+    out: &Path,
+) -> Result<(), InstallError> {
+    let missing: Vec<&str> = fs.iter()
+        .filter(|f| !root.dir(f).join(".git").exists())
+        .map(|f| f.repo.as_str())
+        .collect();
+    if !missing.is_empty() {
+        let msg = format!("not installed: {}", missing.join(", "));
+        return Err(io::Error::new(io::ErrorKind::NotFound, msg).into());
+    }
+    let dir = staging_dir(out);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    let r = export_to(flavor_file, fs, root, &dir).and_then(|_| {
+        tar(out, &["-cf", path_str(out)?, "-C", path_str(&dir)?, "."])
+    });
+    fs::remove_dir_all(&dir)?;
+    r
+}
+
+fn export_to(
+    flavor_file: &str,
+    fs: &[Flavor],
+    root: &Root,
+    dir: &Path,
+) -> Result<(), InstallError> {
+    let cache = dir.join(CACHE);
+    fs::create_dir_all(&cache)?;
+    fs::write(dir.join(FLAVOR_FILE), flavor_file)?;
+    fs::write(dir.join(LOCKFILE), lockfile(fs, root)?)?;
+    for f in fs {
+        let m = cache::entry(&cache, &f.repo);
+        if !m.exists() {
+            let args = ["init", "--quiet", "--bare", path_str(&m)?];
+            git::exec(&f.repo, Operation::Clone, &cache, &args)?;
+        }
+        let refspec = format!("+HEAD:refs/heads/{}", f.branch);
+        let d = root.dir(f);
+        git::exec(
+            &f.repo,
+            Operation::Fetch,
+            &m,
+            &["fetch", "--quiet", "--update-shallow", path_str(&d)?, &refspec],
+        )?;
+    }
+    Ok(())
+}
+
+/// Extracts a bundle written by `export_bundle` from `archive` into `dir`, and renames its
+/// mirrors after the URLs resolved with `opts`, so that `install` can find them.
+pub fn extract_bundle(archive: &Path, dir: &Path, opts: &Options) -> Result<Bundle, InstallError> {
+    fs::create_dir_all(dir)?;
+    tar(archive, &["-xf", path_str(archive)?, "-C", path_str(dir)?])?;
+    let flavor_file = fs::read_to_string(dir.join(FLAVOR_FILE))?;
+    let lockfile = fs::read_to_string(dir.join(LOCKFILE))?;
+    let mut flavors = Parser::new(&flavor_file).parse()?;
+    apply_lockfile(&mut flavors, &lockfile);
+    let cache = dir.join(CACHE);
+    for f in &flavors {
+        let from = cache::entry(&cache, &f.repo);
+        let to = cache::entry(&cache, &url(&f.repo, opts));
+        if from.exists() && !to.exists() {
+            fs::rename(from, to)?;
+        }
+    }
+    Ok(Bundle {
+        flavor_file,
+        lockfile,
+        flavors,
+        cache,
+    })
+}
+
+/// Returns a temporary sibling of `out`, where the contents of a bundle are prepared.
+fn staging_dir(out: &Path) -> PathBuf {
+    let name = out.file_name().map_or("bundle".into(), |n| n.to_string_lossy());
+    out.with_file_name(format!(".tmp-{}", name))
+}
+
+fn tar(archive: &Path, args: &[&str]) -> Result<(), InstallError> {
+    let output = Command::new("tar").args(args).output()?;
+    if !output.status.success() {
+        let name = archive.display().to_string();
+        return Err(CommandError::new(&name, Operation::Archive, "tar", args, &output).into());
+    }
+    Ok(())
+}
+
+/// Returns `p` as a string for arguments of commands, which must be valid UTF-8.
+fn path_str(p: &Path) -> io::Result<&str> {
+    p.to_str().ok_or_else(|| {
+        let msg = format!("{}: path is not valid UTF-8", p.display());
+        io::Error::new(io::ErrorKind::InvalidInput, msg)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Layout, install};
+    use testing;
+
+    #[test]
+    fn test_bundle() {
+        let dir = testing::temp_dir("rust-vim-flavor-bundle-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let url = repo.to_str().unwrap();
+        let flavor_file = format!("flavor '{}'\n", url);
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let flavors = [Flavor::new(url)];
+        install(&flavors, &root, &Options::default()).unwrap();
+        let head = testing::git(&root.dir(&flavors[0]), &["rev-parse", "HEAD"]);
+        let out = dir.join("out.tar");
+        let r1 = export_bundle(&flavor_file, &flavors, &root, &out);
+        let r2 = export_bundle(&flavor_file, &[Flavor::new("a")], &root, &out);
+
+        testing::commit(&repo, "plugin/a.vim", "Second commit");
+        fs::rename(&repo, dir.join("gone")).unwrap();
+        // Where the bundle is extracted, the repository is fetched from a mirror.
+        let rewrite = Options {
+            rewrites: vec![(url.to_owned(), "/srv/mirror".to_owned())],
+            ..Options::default()
+        };
+        let b = extract_bundle(&out, &dir.join("extracted"), &rewrite).unwrap();
+        let opts = Options {
+            cache: Some(b.cache.clone()),
+            offline: true,
+            ..rewrite
+        };
+        let imported = Root::new(dir.join("imported"), Layout::Flat);
+        let r3 = install(&b.flavors, &imported, &opts);
+        let d = imported.dir(&b.flavors[0]);
+        let imported_head = testing::git(&d, &["rev-parse", "HEAD"]);
+        let origin = testing::git(&d, &["remote", "get-url", "origin"]);
+        let staged = dir.join(".tmp-out.tar").exists();
+        if let Some(e) = fs::remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert!(r1.is_ok());
+        assert!(r2.is_err());
+        assert!(!staged);
+        assert_eq!(b.flavor_file, flavor_file);
+        assert_eq!(b.flavors[0].commit, Some(head.clone()));
+        assert!(r3.is_ok());
+        assert_eq!(imported_head, head);
+        assert_eq!(origin, "/srv/mirror");
+    }
+}
@@ -0,0 +1,208 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use {InstallError, Lock, Operation, Options};
+use git;
+
+/// Gets the default directory of the cache of bare repositories.
+/// Returns `$XDG_CACHE_HOME/vim-flavor/git`, or `$HOME/.cache/vim-flavor/git` if
+/// `XDG_CACHE_HOME` is not set. If neither is available, returns None.
+pub fn get_cache_path() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|h| h.join(".cache")))
+        .map(|mut p| {
+            p.push("vim-flavor");
+            p.push("git");
+            p
+        })
+}
+
+/// The file in each entry which records when it was last used.
+const USED: &str = "vim-flavor-used";
+
+/// Fetches `url` of the flavor `name` into its bare mirror in `cache`, cloning the mirror if
+/// missing, and returns the path of the mirror. Progress is sent to `opts.observer`.
+/// The mirror is locked, since the cache is shared among roots. The lock is returned too,
+/// so that the mirror is not removed by `remove_unused` while the caller uses it.
+pub fn mirror(
+    name: &str,
+    cache: &Path,
+    url: &str,
+    opts: &Options,
+) -> Result<(PathBuf, Lock), InstallError> {
+    fs::create_dir_all(cache)?;
+    let lock = lock(cache, url, opts)?;
+    let dir = entry(cache, url);
+    if dir.exists() {
+        let args = ["fetch", "--quiet", "--prune", "origin"];
+        git::exec_progress(name, Operation::Fetch, &dir, &args, opts)?;
+    } else {
+        let tmp = cache.join(format!(".tmp-{}", entry_name(url)));
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        let dest = tmp.to_str().expect("failed to build path for the cache");
+        let args = ["clone", "--quiet", "--mirror", url, dest];
+        let r = git::exec_progress(name, Operation::Clone, cache, &args, opts);
+        if r.is_err() && tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        r?;
+        fs::rename(&tmp, &dir)?;
+    }
+    fs::File::create(dir.join(USED))?.write_all(
+        now().to_string().as_bytes(),
+    )?;
+    Ok((dir, lock))
+}
+
+/// Returns the path of the mirror of `url` in `cache`, whether it exists or not.
+pub fn entry(cache: &Path, url: &str) -> PathBuf {
+    cache.join(entry_name(url))
+}
+
+/// Returns the entries in `cache` which have not been used for `days` days.
+/// Temporary directories of mirrors being cloned are not entries.
+pub fn unused(cache: &Path, days: u64) -> io::Result<Vec<PathBuf>> {
+    if !cache.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(cache)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if is_unused(&path, days) {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Reports whether the entry `dir` has not been used for `days` days.
+fn is_unused(dir: &Path, days: u64) -> bool {
+    let used = fs::read_to_string(dir.join(USED))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0);
+    used <= now().saturating_sub(days * 86400)
+}
+
+/// Removes the entries in `cache` which have not been used for `days` days, and returns them.
+/// Entries which are being fetched or cloned from are skipped, and so are ones which were
+/// used after they were listed.
+pub fn remove_unused(cache: &Path, days: u64) -> io::Result<Vec<PathBuf>> {
+    remove(cache, unused(cache, days)?, days)
+}
+
+/// Removes `dirs`, the entries in `cache` which were listed as unused for `days` days,
+/// checking each again after locking it.
+fn remove(cache: &Path, dirs: Vec<PathBuf>, days: u64) -> io::Result<Vec<PathBuf>> {
+    let opts = Options {
+        wait: false,
+        ..Options::default()
+    };
+    let mut removed = Vec::new();
+    for dir in dirs {
+        let name = dir.file_name().map_or("".into(), |n| n.to_string_lossy().into_owned());
+        let lock = cache.join(format!(".lock-{}", name));
+        match Lock::file(&lock, &dir.to_string_lossy(), &opts) {
+            Ok(_lock) => {
+                if !dir.exists() || !is_unused(&dir, days) {
+                    continue;
+                }
+                // The lock file is left, since another process may be waiting on it.
+                fs::remove_dir_all(&dir)?;
+                removed.push(dir);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(removed)
+}
+
+/// Locks the entry for `url` in `cache`, which must exist.
+pub fn lock(cache: &Path, url: &str, opts: &Options) -> io::Result<Lock> {
+    let path = cache.join(format!(".lock-{}", entry_name(url)));
+    Lock::file(&path, url, opts)
+}
+
+/// Names the entry for `url` after its FNV-1a hash.
+fn entry_name(url: &str) -> String {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in url.bytes() {
+        h ^= u64::from(b);
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", h)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing;
+
+    #[test]
+    fn test_entry_name() {
+        assert_eq!(entry_name(""), "cbf29ce484222325");
+        assert_eq!(entry_name("a"), "af63dc4c8601ec8c");
+        assert_ne!(entry_name("https://a/b"), entry_name("https://a/c"));
+    }
+
+    #[test]
+    fn test_mirror() {
+        let dir = testing::temp_dir("rust-vim-flavor-cache-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let cache = dir.join("cache");
+        let url = repo.to_str().unwrap();
+
+        let (m, _) = mirror("plugin", &cache, url, &Options::default()).unwrap();
+        let first = testing::git(&m, &["rev-parse", "master"]);
+        let second = testing::commit(&repo, "plugin/a.vim", "Second commit");
+        mirror("plugin", &cache, url, &Options::default()).unwrap();
+        let fetched = testing::git(&m, &["rev-parse", "master"]);
+        let fresh = unused(&cache, 1).unwrap();
+        fs::write(m.join(USED), "0").unwrap();
+        // A mirror being cloned is not an entry yet.
+        fs::create_dir_all(cache.join(format!(".tmp-{}", entry_name("b")))).unwrap();
+        let stale = unused(&cache, 1).unwrap();
+        let busy = {
+            let _lock = lock(&cache, url, &Options::default()).unwrap();
+            remove_unused(&cache, 1).unwrap()
+        };
+        // An entry which is used after it is listed is kept.
+        let listed = unused(&cache, 1).unwrap();
+        mirror("plugin", &cache, url, &Options::default()).unwrap();
+        let kept = remove(&cache, listed, 1).unwrap();
+        fs::write(m.join(USED), "0").unwrap();
+        let removed = remove_unused(&cache, 1).unwrap();
+        let exists = m.exists();
+        if let Some(e) = fs::remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert_eq!(m, cache.join(entry_name(url)));
+        assert_ne!(first, second);
+        assert_eq!(fetched, second);
+        assert!(fresh.is_empty());
+        assert_eq!(stale, vec![m.clone()]);
+        assert!(busy.is_empty());
+        assert!(kept.is_empty());
+        assert_eq!(removed, vec![m]);
+        assert!(!exists);
+    }
+}
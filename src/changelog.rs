@@ -0,0 +1,95 @@
+use std::io;
+use std::path::Path;
+
+use git;
+
+#[derive(Debug, PartialEq)]
+/// Represents commits which were pulled into a plugin.
+pub struct Change {
+    /// The repository of the plugin as written in VimFlavor file.
+    pub repo: String,
+    /// The commit checked out before pulling.
+    pub before: String,
+    /// The commit checked out after pulling.
+    pub after: String,
+    /// The pulled commits, newest first. Empty if `recloned`.
+    pub commits: Vec<Commit>,
+    /// Whether the plugin was re-cloned from another URL, in which `before` is not found,
+    /// so the pulled commits are unknown.
+    pub recloned: bool,
+}
+
+#[derive(Debug, PartialEq)]
+/// Represents a pulled commit.
+pub struct Commit {
+    /// The abbreviated name of the commit.
+    pub id: String,
+    /// The first line of the commit message.
+    pub subject: String,
+    /// Whether the commit may break something, that is, it touches `doc/` or its message
+    /// contains "BREAKING".
+    pub breaking: bool,
+}
+
+/// Returns the commits in `before..after` of the repository at `dir`.
+pub fn commits(dir: &Path, before: &str, after: &str) -> io::Result<Vec<Commit>> {
+    let range = format!("{}..{}", before, after);
+    let log = git::read(
+        dir,
+        &["log", "--format=%x00%h %s%n%b%x00", "--name-only", &range],
+    )?;
+    Ok(parse_log(&log.unwrap_or_default()))
+}
+
+/// Parses the output of `git log --format=%x00%h %s%n%b%x00 --name-only`.
+fn parse_log(log: &str) -> Vec<Commit> {
+    let mut commits = Vec::new();
+    let mut chunks = log.split('\0').skip(1);
+    while let Some(message) = chunks.next() {
+        let files = chunks.next().unwrap_or("");
+        let mut header = message.lines().next().unwrap_or("").splitn(2, ' ');
+        let id = header.next().unwrap_or("").to_owned();
+        let subject = header.next().unwrap_or("").to_owned();
+        let breaking = message.contains("BREAKING") ||
+            files.lines().any(|f| f.starts_with("doc/"));
+        commits.push(Commit {
+            id,
+            subject,
+            breaking,
+        });
+    }
+    commits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log() {
+        let log = "\0abc1234 Update docs\n\n\0\n\ndoc/a.txt\nplugin/a.vim\n\
+                   \0def5678 Rename options\nBREAKING CHANGE: g:a is removed.\n\0\n\nplugin/a.vim\n\
+                   \0aaa0000 Fix a bug\n\n\0\n\nplugin/a.vim";
+        assert_eq!(
+            parse_log(log),
+            vec![
+                Commit {
+                    id: "abc1234".to_owned(),
+                    subject: "Update docs".to_owned(),
+                    breaking: true,
+                },
+                Commit {
+                    id: "def5678".to_owned(),
+                    subject: "Rename options".to_owned(),
+                    breaking: true,
+                },
+                Commit {
+                    id: "aaa0000".to_owned(),
+                    subject: "Fix a bug".to_owned(),
+                    breaking: false,
+                },
+            ]
+        );
+        assert_eq!(parse_log(""), vec![]);
+    }
+}
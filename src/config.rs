@@ -0,0 +1,416 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use {Hooks, Protocol, Target};
+
+/// Gets the path of the configuration file.
+/// Returns `$XDG_CONFIG_HOME/vim-flavor/config.toml`, or `$HOME/.config/vim-flavor/config.toml`
+/// if `XDG_CONFIG_HOME` is not set. If neither is available, returns None.
+pub fn get_config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|h| h.join(".config")))
+        .map(|mut p| {
+            p.push("vim-flavor");
+            p.push("config.toml");
+            p
+        })
+}
+
+#[derive(Debug, Default, PartialEq)]
+/// Settings read from the configuration file, which is written in a subset of TOML.
+/// Settings which are not in the file are None.
+///
+/// ```toml
+/// target = "vim"
+/// root = "~/.vim/rflavors"
+/// file = "VimFlavor"
+/// jobs = 4
+/// depth = 1
+/// protocol = "https"
+/// host = "github.com"
+/// vim_scripts = "vim-scripts"
+/// cache = "~/.cache/vim-flavor/git"
+///
+/// [rewrite]
+/// "https://github.com/" = "file:///srv/mirrors/github/"
+///
+/// [hooks]
+/// post_install = "make"
+/// post_update = "make"
+/// ```
+pub struct Config {
+    /// The editor into which plugins are deployed.
+    pub target: Option<Target>,
+    /// The root directory, where a leading `~` is expanded to the home directory.
+    pub root: Option<PathBuf>,
+    /// The name of the flavor file.
+    pub file: Option<String>,
+    /// The number of plugins installed or updated at a time.
+    pub jobs: Option<usize>,
+    /// The depth of clones; 0 means full clones.
+    pub depth: Option<u32>,
+    /// The protocol for repositories written in shorthand.
+    pub protocol: Option<Protocol>,
+    /// The host for repositories written in shorthand.
+    pub host: Option<String>,
+    /// The user who hosts repositories written as a single name; empty means none.
+    pub vim_scripts: Option<String>,
+    /// The directory of the cache of bare repositories, where a leading `~` is expanded to
+    /// the home directory; empty means no cache.
+    pub cache: Option<PathBuf>,
+    /// Pairs of a URL prefix and its replacement, in the `rewrite` table.
+    pub rewrites: Vec<(String, String)>,
+    /// Commands in the `hooks` table.
+    pub hooks: Hooks,
+}
+
+impl Config {
+    /// Returns the root given by `value`, which comes from a flag or an environment variable,
+    /// or otherwise the one in the configuration. An empty value or root is unset, so that
+    /// it falls back rather than meaning the current directory.
+    pub fn resolve_root(&self, value: Option<String>) -> Option<PathBuf> {
+        value
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| self.root.clone())
+            .filter(|p| !p.as_os_str().is_empty())
+    }
+
+    /// Parses content of the configuration file.
+    pub fn parse(s: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut table = String::new();
+        for (n, line) in s.lines().enumerate() {
+            let n = n + 1;
+            let line = match find_unquoted(line, '#') {
+                Some(i) => line[..i].trim(),
+                None => line.trim(),
+            };
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(ConfigError::Syntax(n, "unterminated table header"));
+                }
+                table = line[1..line.len() - 1].trim().to_owned();
+                continue;
+            }
+            let eq = find_unquoted(line, '=').ok_or(
+                ConfigError::Syntax(n, "missing '='"),
+            )?;
+            let key = parse_key(line[..eq].trim()).ok_or(
+                ConfigError::Syntax(n, "invalid key"),
+            )?;
+            let value = parse_value(line[eq + 1..].trim()).ok_or(
+                ConfigError::Syntax(n, "invalid value"),
+            )?;
+            config.set(n, &table, key, value)?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, n: usize, table: &str, key: String, value: Value) -> Result<(), ConfigError> {
+        match (table, key.as_str()) {
+            ("", "target") => {
+                let t = Target::from_name(&value.string(n)?);
+                let want = "\"vim\" or \"nvim\"";
+                self.target = Some(t.ok_or(ConfigError::TypeMismatch(n, want))?);
+            }
+            ("", "root") => self.root = Some(expand_home(value.string(n)?)),
+            ("", "file") => self.file = Some(value.string(n)?),
+            ("", "jobs") => {
+                let j = value.int(n)?;
+                if j <= 0 {
+                    return Err(ConfigError::TypeMismatch(n, "positive integer"));
+                }
+                self.jobs = Some(j as usize);
+            }
+            ("", "depth") => {
+                let d = value.int(n)?;
+                if d < 0 || d > i64::from(u32::max_value()) {
+                    return Err(ConfigError::TypeMismatch(n, "non-negative integer"));
+                }
+                self.depth = Some(d as u32);
+            }
+            ("", "protocol") => {
+                let p = Protocol::from_name(&value.string(n)?);
+                let want = "\"https\" or \"ssh\"";
+                self.protocol = Some(p.ok_or(ConfigError::TypeMismatch(n, want))?);
+            }
+            ("", "host") => self.host = Some(value.string(n)?),
+            ("", "vim_scripts") => self.vim_scripts = Some(value.string(n)?),
+            ("", "cache") => self.cache = Some(expand_home(value.string(n)?)),
+            ("rewrite", _) => self.rewrites.push((key, value.string(n)?)),
+            ("hooks", "post_install") => self.hooks.post_install = Some(value.string(n)?),
+            ("hooks", "post_update") => self.hooks.post_update = Some(value.string(n)?),
+            ("", _) => return Err(ConfigError::UnknownKey(n, key)),
+            _ => return Err(ConfigError::UnknownKey(n, format!("{}.{}", table, key))),
+        }
+        Ok(())
+    }
+}
+
+fn expand_home(path: String) -> PathBuf {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = env::home_dir() {
+            return home.join(path[1..].trim_matches('/'));
+        }
+    }
+    PathBuf::from(path)
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl Value {
+    fn string(self, n: usize) -> Result<String, ConfigError> {
+        match self {
+            Value::Str(s) => Ok(s),
+            _ => Err(ConfigError::TypeMismatch(n, "string")),
+        }
+    }
+
+    fn int(self, n: usize) -> Result<i64, ConfigError> {
+        match self {
+            Value::Int(i) => Ok(i),
+            _ => Err(ConfigError::TypeMismatch(n, "integer")),
+        }
+    }
+}
+
+/// Finds `target` which is not in a string literal.
+fn find_unquoted(line: &str, target: char) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        match (quote, ch) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (None, ch) if ch == target => return Some(i),
+            (None, '"') | (None, '\'') => quote = Some(ch),
+            (Some(q), _) if q == ch && !escaped => quote = None,
+            _ => (),
+        }
+        escaped = false;
+    }
+    None
+}
+
+fn parse_key(s: &str) -> Option<String> {
+    if s.starts_with('"') || s.starts_with('\'') {
+        return match parse_value(s)? {
+            Value::Str(k) => Some(k),
+            _ => None,
+        };
+    }
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return None;
+    }
+    Some(s.to_owned())
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    if s.len() >= 2 && s.starts_with('\'') && s.ends_with('\'') {
+        let body = &s[1..s.len() - 1];
+        if body.contains('\'') {
+            return None;
+        }
+        return Some(Value::Str(body.to_owned()));
+    }
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        let mut body = String::new();
+        let mut chars = s[1..s.len() - 1].chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => return None,
+                '\\' => {
+                    body.push(match chars.next()? {
+                        'n' => '\n',
+                        't' => '\t',
+                        '"' => '"',
+                        '\\' => '\\',
+                        _ => return None,
+                    })
+                }
+                ch => body.push(ch),
+            }
+        }
+        return Some(Value::Str(body));
+    }
+    match s {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => s.parse().ok().map(Value::Int),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// An error while parsing the configuration file.
+pub enum ConfigError {
+    /// Invalid syntax at the line.
+    Syntax(usize, &'static str),
+    /// Unknown key at the line.
+    UnknownKey(usize, String),
+    /// The value at the line is not of the type.
+    TypeMismatch(usize, &'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Syntax(n, msg) => write!(f, "line {}: {}", n, msg),
+            ConfigError::UnknownKey(n, ref key) => write!(f, "line {}: unknown key: {}", n, key),
+            ConfigError::TypeMismatch(n, want) => write!(f, "line {}: want {}", n, want),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::Syntax(..) => "invalid syntax",
+            ConfigError::UnknownKey(..) => "unknown key",
+            ConfigError::TypeMismatch(..) => "type mismatch",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let s = "# mirrors\n\
+                 [rewrite]\n\
+                 \"https://github.com/\" = \"file:///srv/mirrors/github/\" # comment\n\
+                 'git@host:' = 'https://host/#/'\n\
+                 \"a\\\"#=\" = \"b\"\n";
+        assert_eq!(
+            Config::parse(s),
+            Ok(Config {
+                target: None,
+                root: None,
+                file: None,
+                jobs: None,
+                depth: None,
+                protocol: None,
+                host: None,
+                vim_scripts: None,
+                cache: None,
+                hooks: Hooks::default(),
+                rewrites: vec![
+                    (
+                        "https://github.com/".to_owned(),
+                        "file:///srv/mirrors/github/".to_owned(),
+                    ),
+                    ("git@host:".to_owned(), "https://host/#/".to_owned()),
+                    ("a\"#=".to_owned(), "b".to_owned()),
+                ],
+            })
+        );
+
+        let s = "target = 'nvim'\n\
+                 root = '/vim/flavors'\n\
+                 jobs = 4\n\
+                 depth = 0\n\
+                 protocol = 'ssh'\n\
+                 [hooks]\n\
+                 post_update = 'make'\n";
+        assert_eq!(
+            Config::parse(s),
+            Ok(Config {
+                target: Some(Target::Nvim),
+                root: Some(PathBuf::from("/vim/flavors")),
+                jobs: Some(4),
+                depth: Some(0),
+                protocol: Some(Protocol::Ssh),
+                hooks: Hooks {
+                    post_install: None,
+                    post_update: Some("make".to_owned()),
+                },
+                ..Config::default()
+            })
+        );
+
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+        assert_eq!(
+            Config::parse("jobs = 0"),
+            Err(ConfigError::TypeMismatch(1, "positive integer"))
+        );
+        assert_eq!(
+            Config::parse("protocol = 'git'"),
+            Err(ConfigError::TypeMismatch(1, "\"https\" or \"ssh\""))
+        );
+        assert_eq!(
+            Config::parse("[hooks]\npre_install = 'make'"),
+            Err(ConfigError::UnknownKey(2, "hooks.pre_install".to_owned()))
+        );
+        assert_eq!(
+            Config::parse("[rewrite\n"),
+            Err(ConfigError::Syntax(1, "unterminated table header"))
+        );
+        assert_eq!(
+            Config::parse("\n[rewrite]\na = 1"),
+            Err(ConfigError::TypeMismatch(3, "string"))
+        );
+        assert_eq!(
+            Config::parse("a = 'b'"),
+            Err(ConfigError::UnknownKey(1, "a".to_owned()))
+        );
+        assert_eq!(
+            Config::parse("[rewrite]\na = \"b"),
+            Err(ConfigError::Syntax(2, "invalid value"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_root() {
+        let config = Config {
+            root: Some(PathBuf::from("/r")),
+            ..Config::default()
+        };
+        assert_eq!(config.resolve_root(Some("/x".to_owned())), Some(PathBuf::from("/x")));
+        assert_eq!(config.resolve_root(None), Some(PathBuf::from("/r")));
+        // An empty value does not hide the root in the configuration.
+        assert_eq!(config.resolve_root(Some("".to_owned())), Some(PathBuf::from("/r")));
+        let config = Config {
+            root: Some(PathBuf::new()),
+            ..Config::default()
+        };
+        assert_eq!(config.resolve_root(Some("".to_owned())), None);
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = env::home_dir().unwrap();
+        assert_eq!(expand_home("~/.vim".to_owned()), home.join(".vim"));
+        assert_eq!(expand_home("~".to_owned()), home);
+        assert_eq!(expand_home("/~/a".to_owned()), PathBuf::from("/~/a"));
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("\"a\\\"b\\\\\""), Some(Value::Str("a\"b\\".to_owned())));
+        assert_eq!(parse_value("'a\\b'"), Some(Value::Str("a\\b".to_owned())));
+        assert_eq!(parse_value("true"), Some(Value::Bool(true)));
+        assert_eq!(parse_value("-3"), Some(Value::Int(-3)));
+        assert_eq!(parse_value("a"), None);
+        assert_eq!(parse_value("\"a\"b\""), None);
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+/// An event while installing, updating or rolling back a plugin, which is sent to
+/// `Options::observer`.
+pub enum Event {
+    /// Started installing or updating the plugin.
+    Started,
+    /// Cloning the plugin from the URL or path.
+    Cloning(String),
+    /// Fetching the plugin from the URL or path.
+    Fetching(String),
+    /// Progress of cloning or fetching: a phase reported by git, like "Receiving objects",
+    /// and its percentage.
+    Progress(String, u8),
+    /// The remote of the plugin is changed from the URL, if any, to another.
+    Repointed(Option<String>, String),
+    /// Local modifications of the plugin are discarded.
+    Discarding,
+    /// Installed at the commit.
+    Installed(String),
+    /// Already up to date at the commit.
+    UpToDate(String),
+    /// Updated from the commit to another.
+    Updated(String, String),
+    /// Skipped because the plugin is not installed yet.
+    Skipped,
+    /// A line which a hook wrote to its standard output or error.
+    HookOutput(String),
+    /// Failed with the message of the error, which is also returned.
+    Failed(String),
+    /// A notice about the root, like waiting for its lock.
+    Notice(String),
+}
+
+/// Receives events while installing or updating plugins.
+/// It may be notified from several threads at once when plugins are handled in parallel.
+pub trait Observer: fmt::Debug + Send + Sync {
+    /// Receives `event` on `name`, which is the repository of a flavor, the directory of a
+    /// checkout for `rollback`, or the path of the root for `Event::Notice`.
+    fn notify(&self, name: &str, event: &Event);
+}
+
+/// Parses a line of progress which git writes with `--progress`, like
+/// "Receiving objects:  45% (9/20)", into its phase and percentage.
+pub fn parse_progress(line: &str) -> Option<(String, u8)> {
+    let line = if line.starts_with("remote: ") {
+        &line["remote: ".len()..]
+    } else {
+        line
+    };
+    let colon = line.find(": ")?;
+    let rest = line[colon + 2..].trim();
+    let percent = rest.find('%')?;
+    let n = rest[..percent].parse().ok().filter(|&n| n <= 100)?;
+    Some((line[..colon].to_owned(), n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_progress() {
+        assert_eq!(
+            parse_progress("Receiving objects:  45% (9/20), 1.00 KiB | 1.00 MiB/s"),
+            Some(("Receiving objects".to_owned(), 45))
+        );
+        assert_eq!(
+            parse_progress("remote: Counting objects: 100% (3/3), done."),
+            Some(("Counting objects".to_owned(), 100))
+        );
+        assert_eq!(parse_progress("Cloning into 'a'..."), None);
+        assert_eq!(parse_progress("fatal: 120% sure"), None);
+        assert_eq!(parse_progress("warning: see 50%"), None);
+    }
+}
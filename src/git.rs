@@ -0,0 +1,86 @@
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use {CommandError, Event, InstallError, Operation, Options};
+use event::parse_progress;
+
+/// Runs git with `args` in `dir`.
+pub fn run(dir: &Path, args: &[&str]) -> io::Result<Output> {
+    Command::new("git").current_dir(dir).args(args).output()
+}
+
+/// Runs git with `args` in `dir` and returns its trimmed standard output.
+/// Returns None if git exits unsuccessfully.
+pub fn read(dir: &Path, args: &[&str]) -> io::Result<Option<String>> {
+    let output = run(dir, args)?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).trim().to_owned()))
+}
+
+/// Runs git with `args` in `dir` for `operation` on `name`. If it fails, returns an error
+/// with its standard error.
+pub fn exec(
+    name: &str,
+    operation: Operation,
+    dir: &Path,
+    args: &[&str],
+) -> Result<(), InstallError> {
+    let output = run(dir, args)?;
+    if !output.status.success() {
+        return Err(CommandError::new(name, operation, "git", args, &output).into());
+    }
+    Ok(())
+}
+
+/// Runs git with `args` in `dir` as `exec` does, and reports its progress to the observer in
+/// `opts`. The command in `args`, like clone or fetch, must accept `--progress`.
+pub fn exec_progress(
+    name: &str,
+    operation: Operation,
+    dir: &Path,
+    args: &[&str],
+    opts: &Options,
+) -> Result<(), InstallError> {
+    let mut argv = vec![args[0], "--progress"];
+    argv.extend(&args[1..]);
+    let mut child = Command::new("git")
+        .current_dir(dir)
+        .args(&argv)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stderr = Vec::new();
+    let mut last = None;
+    // Progress is rewritten in place with carriage returns.
+    let reader = BufReader::new(child.stderr.take().expect("stderr is piped"));
+    for line in reader.split(b'\n') {
+        for l in line?.split(|&b| b == b'\r') {
+            match parse_progress(&String::from_utf8_lossy(l)) {
+                Some(p) => {
+                    if last.as_ref() != Some(&p) {
+                        opts.notify(name, &Event::Progress(p.0.clone(), p.1));
+                        last = Some(p);
+                    }
+                }
+                None if !l.is_empty() => {
+                    stderr.extend(l);
+                    stderr.push(b'\n');
+                }
+                None => (),
+            }
+        }
+    }
+    let output = Output {
+        status: child.wait()?,
+        stdout: Vec::new(),
+        stderr,
+    };
+    if !output.status.success() {
+        return Err(CommandError::new(name, operation, "git", &argv, &output).into());
+    }
+    Ok(())
+}
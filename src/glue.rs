@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use super::{Layout, Root};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The language of glue scripts.
+pub enum Script {
+    /// Vim script, to be sourced from `vimrc` or `init.vim`.
+    Vim,
+    /// Lua, to be loaded from `init.lua` of Neovim.
+    Lua,
+}
+
+/// Returns a script which makes Vim or Neovim load plugins under `root`.
+///
+/// For `Layout::Flat`, the script prepends each directory under the root to `runtimepath`.
+/// For `Layout::Pack`, it prepends the parent of the `pack` directory to `packpath`, so the
+/// root must be named like `<dir>/pack/<name>`; otherwise, returns None.
+pub fn glue(root: &Root, script: Script) -> Option<String> {
+    let path = root.path();
+    let mut s = String::new();
+    match (root.layout(), script) {
+        (Layout::Flat, Script::Vim) => {
+            s.push_str("\" Generated by vim-flavor.\n");
+            s.push_str(&format!(
+                "for s:dir in glob({} . '/*', 1, 1)\n",
+                vim_quote(path)
+            ));
+            s.push_str(
+                "  let &runtimepath = escape(s:dir, '\\,') . ',' . &runtimepath\n\
+                 \x20 if isdirectory(s:dir . '/after')\n\
+                 \x20   let &runtimepath .= ',' . escape(s:dir . '/after', '\\,')\n\
+                 \x20 endif\n\
+                 endfor\n\
+                 unlet! s:dir\n",
+            );
+        }
+        (Layout::Flat, Script::Lua) => {
+            s.push_str("-- Generated by vim-flavor.\n");
+            s.push_str(&format!(
+                "for _, dir in ipairs(vim.fn.glob({} .. '/*', true, true)) do\n",
+                lua_quote(path)
+            ));
+            s.push_str(
+                "  vim.o.runtimepath = vim.fn.escape(dir, '\\\\,') .. ',' .. vim.o.runtimepath\n\
+                 \x20 if vim.fn.isdirectory(dir .. '/after') == 1 then\n\
+                 \x20   vim.o.runtimepath = vim.o.runtimepath .. ',' ..\n\
+                 \x20     vim.fn.escape(dir .. '/after', '\\\\,')\n\
+                 \x20 end\n\
+                 end\n",
+            );
+        }
+        (Layout::Pack, script) => {
+            let pack = path.parent()?;
+            if pack.file_name()? != "pack" {
+                return None;
+            }
+            let dir = pack.parent()?;
+            match script {
+                Script::Vim => {
+                    s.push_str("\" Generated by vim-flavor.\n");
+                    s.push_str(&format!(
+                        "let &packpath = escape({}, '\\,') . ',' . &packpath\n",
+                        vim_quote(dir)
+                    ));
+                }
+                Script::Lua => {
+                    s.push_str("-- Generated by vim-flavor.\n");
+                    s.push_str(&format!(
+                        "vim.o.packpath = vim.fn.escape({}, '\\\\,') .. ',' .. vim.o.packpath\n",
+                        lua_quote(dir)
+                    ));
+                }
+            }
+        }
+    }
+    Some(s)
+}
+
+/// Quotes `path` as a literal string of Vim script.
+fn vim_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "''"))
+}
+
+/// Quotes `path` as a string of Lua.
+fn lua_quote(path: &Path) -> String {
+    let mut q = String::from("'");
+    for ch in path.to_string_lossy().chars() {
+        match ch {
+            '\\' | '\'' => {
+                q.push('\\');
+                q.push(ch);
+            }
+            '\n' => q.push_str("\\n"),
+            ch => q.push(ch),
+        }
+    }
+    q.push('\'');
+    q
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glue() {
+        let flat = Root::new("/home/it's/.vim/rflavors", Layout::Flat);
+        let s = glue(&flat, Script::Vim).unwrap();
+        assert!(s.contains("for s:dir in glob('/home/it''s/.vim/rflavors' . '/*', 1, 1)\n"));
+        let s = glue(&flat, Script::Lua).unwrap();
+        assert!(s.contains("ipairs(vim.fn.glob('/home/it\\'s/.vim/rflavors' .. '/*', true, true))"));
+
+        let pack = Root::new("/data/nvim/site/pack/rflavors", Layout::Pack);
+        assert_eq!(
+            glue(&pack, Script::Vim).unwrap(),
+            "\" Generated by vim-flavor.\n\
+             let &packpath = escape('/data/nvim/site', '\\,') . ',' . &packpath\n"
+        );
+        assert_eq!(
+            glue(&pack, Script::Lua).unwrap(),
+            "-- Generated by vim-flavor.\n\
+             vim.o.packpath = vim.fn.escape('/data/nvim/site', '\\\\,') .. ',' .. vim.o.packpath\n"
+        );
+
+        assert_eq!(glue(&Root::new("/rflavors", Layout::Pack), Script::Vim), None);
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Generates `tags` for `*.txt` and `tags-xx` for `*.xxx` in `doc`, as `:helptags` does.
+pub fn helptags(doc: &Path) -> io::Result<()> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(doc)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            if let Ok(name) = entry.file_name().into_string() {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+
+    let mut langs: Vec<String> = names.iter().filter_map(|n| lang_of(n)).collect();
+    langs.sort();
+    langs.dedup();
+    for lang in langs {
+        let files: Vec<&String> = names
+            .iter()
+            .filter(|n| lang_of(n).as_ref() == Some(&lang))
+            .collect();
+        let tagfile = if lang == "en" {
+            "tags".to_owned()
+        } else {
+            format!("tags-{}", lang)
+        };
+        write_tags(doc, &files, &doc.join(tagfile))?;
+    }
+    Ok(())
+}
+
+/// Returns the language of a help file named `name`: "en" for `*.txt`, and "xx" for `*.xxx`.
+fn lang_of(name: &str) -> Option<String> {
+    let n = name.rfind('.')?;
+    let ext = &name[n + 1..];
+    if n == 0 {
+        return None;
+    }
+    if ext == "txt" {
+        return Some("en".to_owned());
+    }
+    let b = ext.as_bytes();
+    let letters = b.len() == 3 && b[..2].iter().all(u8::is_ascii_alphabetic);
+    if letters && (b[2] == b'x' || b[2] == b'X') {
+        return Some(ext[..2].to_ascii_lowercase());
+    }
+    None
+}
+
+/// Writes the tags in `files` into `tagfile`. As Vim does, the tags file is marked as UTF-8
+/// if the first line of any file has non-ASCII characters in UTF-8, and files whose first
+/// lines disagree on the encoding are an error.
+fn write_tags(doc: &Path, files: &[&String], tagfile: &Path) -> io::Result<()> {
+    let mut utf8: Option<bool> = None;
+    let mut tags: Vec<(Vec<u8>, &str)> = Vec::new();
+    for name in files {
+        let mut r = BufReader::new(File::open(doc.join(name))?);
+        let mut line = Vec::new();
+        let mut first = true;
+        while r.read_until(b'\n', &mut line)? > 0 {
+            if first {
+                if line.iter().any(|&b| b >= 0x80) {
+                    let this = String::from_utf8(line.clone()).is_ok();
+                    if utf8.map_or(false, |u| u != this) {
+                        let msg = format!(
+                            "{}: mix of help file encodings within a language",
+                            doc.join(name).display()
+                        );
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                    }
+                    utf8 = Some(this);
+                }
+                first = false;
+            }
+            for tag in tags_in_line(&line) {
+                tags.push((tag.to_vec(), name));
+            }
+            line.clear();
+        }
+    }
+    tags.sort();
+
+    let mut w = io::BufWriter::new(File::create(tagfile)?);
+    if utf8 == Some(true) {
+        w.write_all(b"!_TAG_FILE_ENCODING\tutf-8\t//\n")?;
+    }
+    for (tag, name) in tags {
+        w.write_all(&tag)?;
+        write!(w, "\t{}\t/*", name)?;
+        for b in tag {
+            if b == b'\\' || b == b'/' {
+                w.write_all(b"\\")?;
+            }
+            w.write_all(&[b])?;
+        }
+        w.write_all(b"*\n")?;
+    }
+    w.flush()
+}
+
+/// Extracts `*tag*`s in a line, which are preceded by white space or the start of the line,
+/// and followed by white space or the end of the line.
+fn tags_in_line(line: &[u8]) -> Vec<&[u8]> {
+    let find = |from: usize| line[from..].iter().position(|&b| b == b'*').map(|n| n + from);
+    let mut tags = Vec::new();
+    let mut p1 = find(0);
+    while let Some(i) = p1 {
+        let mut p2 = find(i + 1);
+        if let Some(j) = p2 {
+            let tag = &line[i + 1..j];
+            let valid = j > i + 1 && !tag.iter().any(|&b| b == b' ' || b == b'\t' || b == b'|') &&
+                (i == 0 || line[i - 1] == b' ' || line[i - 1] == b'\t') &&
+                line.get(j + 1).map_or(true, |&b| b" \t\n\r".contains(&b));
+            if valid {
+                tags.push(tag);
+                p2 = find(j + 1);
+            }
+        }
+        p1 = p2;
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn test_lang_of() {
+        assert_eq!(lang_of("vspec.txt"), Some("en".to_owned()));
+        assert_eq!(lang_of("vspec.jax"), Some("ja".to_owned()));
+        assert_eq!(lang_of("vspec.ja"), None);
+        assert_eq!(lang_of("tags"), None);
+        assert_eq!(lang_of(".txt"), None);
+        assert_eq!(lang_of("vspec.JAX"), Some("ja".to_owned()));
+        assert_eq!(lang_of("vspec.j1x"), None);
+        assert_eq!(lang_of("vspec.éx"), None);
+    }
+
+    #[test]
+    fn test_tags_in_line() {
+        let tags: Vec<&[u8]> = vec![b"vspec.txt", b"vspec"];
+        assert_eq!(tags_in_line(b"*vspec.txt*\tfor Vim *vspec*\n"), tags);
+
+        let tags: Vec<&[u8]> = vec![b"b"];
+        assert_eq!(tags_in_line(b"a*a* **  *b*"), tags);
+
+        assert!(tags_in_line(b"*a b* *a|b* *a*b").is_empty());
+    }
+
+    #[test]
+    fn test_helptags() {
+        let mut dir = env::temp_dir();
+        dir.push("rust-vim-flavor-helptags-test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b.txt"), "*b.txt*\n*b/c*\n").unwrap();
+        fs::write(dir.join("a.txt"), "*a.txt*\n").unwrap();
+        fs::write(dir.join("a.jax"), "*a.txt*\n").unwrap();
+        // Only the second file in Japanese is in UTF-8.
+        fs::write(dir.join("b.jax"), "*b.txt*\t日本語\n").unwrap();
+
+        let r = helptags(&dir);
+        let tags = fs::read_to_string(dir.join("tags"));
+        let tags_ja = fs::read_to_string(dir.join("tags-ja"));
+        if let Some(e) = fs::remove_dir_all(dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert!(r.is_ok());
+        assert_eq!(
+            tags.unwrap(),
+            "a.txt\ta.txt\t/*a.txt*\n\
+             b.txt\tb.txt\t/*b.txt*\n\
+             b/c\tb.txt\t/*b\\/c*\n"
+        );
+        assert_eq!(
+            tags_ja.unwrap(),
+            "!_TAG_FILE_ENCODING\tutf-8\t//\n\
+             a.txt\ta.jax\t/*a.txt*\n\
+             b.txt\tb.jax\t/*b.txt*\n"
+        );
+    }
+
+    #[test]
+    fn test_helptags_mixed_encodings() {
+        let mut dir = env::temp_dir();
+        dir.push("rust-vim-flavor-helptags-mixed-test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "*a.txt*\t\u{e9}\n").unwrap();
+        fs::write(dir.join("b.txt"), b"*b.txt*\t\xe9\n").unwrap();
+
+        let r = helptags(&dir);
+        if let Some(e) = fs::remove_dir_all(dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert_eq!(r.map_err(|e| e.kind()), Err(io::ErrorKind::InvalidData));
+    }
+}
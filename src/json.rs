@@ -0,0 +1,297 @@
+use std::fmt;
+
+use super::{Change, Event, Status};
+
+#[derive(Debug, Clone, PartialEq)]
+/// A JSON value, which is printed in a single line.
+pub enum Json {
+    /// `null`.
+    Null,
+    /// `true` or `false`.
+    Bool(bool),
+    /// A non-negative integer.
+    Number(u64),
+    /// A string, which is escaped when printed.
+    String(String),
+    /// An array of values.
+    Array(Vec<Json>),
+    /// An object, whose fields are printed in order.
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(ref s) => {
+                write!(f, "\"")?;
+                for ch in s.chars() {
+                    match ch {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        ch if ch < ' ' => write!(f, "\\u{:04x}", ch as u32)?,
+                        ch => write!(f, "{}", ch)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(ref values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (i, &(name, ref v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", Json::String(name.to_owned()), v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(v: Option<T>) -> Json {
+        v.map_or(Json::Null, Into::into)
+    }
+}
+
+/// Returns the object of `event` of the plugin `name`, or of the root `name` for a notice.
+pub fn event_json(name: &str, event: &Event) -> Json {
+    let mut fields = vec![("plugin", name.into())];
+    let (action, rest): (&str, Vec<(&'static str, Json)>) = match *event {
+        Event::Started => ("started", vec![]),
+        Event::Cloning(ref url) => ("cloning", vec![("url", url.as_str().into())]),
+        Event::Fetching(ref url) => ("fetching", vec![("url", url.as_str().into())]),
+        Event::Progress(ref phase, percent) => {
+            (
+                "progress",
+                vec![
+                    ("phase", phase.as_str().into()),
+                    ("percent", Json::Number(percent.into())),
+                ],
+            )
+        }
+        Event::Repointed(ref from, ref to) => {
+            (
+                "repointed",
+                vec![
+                    ("old_url", from.as_ref().map(|u| u.as_str()).into()),
+                    ("new_url", to.as_str().into()),
+                ],
+            )
+        }
+        Event::Discarding => ("discarding", vec![]),
+        Event::Installed(ref c) => {
+            ("installed", vec![("old", Json::Null), ("new", c.as_str().into())])
+        }
+        Event::UpToDate(ref c) => {
+            ("up_to_date", vec![("old", c.as_str().into()), ("new", c.as_str().into())])
+        }
+        Event::Updated(ref a, ref b) => {
+            ("updated", vec![("old", a.as_str().into()), ("new", b.as_str().into())])
+        }
+        Event::Skipped => ("skipped", vec![]),
+        Event::HookOutput(ref line) => ("hook_output", vec![("line", line.as_str().into())]),
+        Event::Failed(ref e) => ("failed", vec![("error", e.as_str().into())]),
+        Event::Notice(ref msg) => {
+            fields = vec![("path", name.into())];
+            ("notice", vec![("message", msg.as_str().into())])
+        }
+    };
+    fields.push(("action", action.into()));
+    fields.extend(rest);
+    Json::Object(fields)
+}
+
+/// Returns the object of the commits pulled into a plugin by `update`.
+pub fn change_json(c: &Change) -> Json {
+    let commits = c.commits
+        .iter()
+        .map(|commit| {
+            Json::Object(vec![
+                ("id", commit.id.as_str().into()),
+                ("subject", commit.subject.as_str().into()),
+                ("breaking", commit.breaking.into()),
+            ])
+        })
+        .collect();
+    Json::Object(vec![
+        ("plugin", c.repo.as_str().into()),
+        ("action", "commits".into()),
+        ("old", c.before.as_str().into()),
+        ("new", c.after.as_str().into()),
+        ("recloned", c.recloned.into()),
+        ("commits", Json::Array(commits)),
+    ])
+}
+
+/// Returns the object of the state of the plugin `repo`, which is None if not installed.
+pub fn status_json(repo: &str, status: Option<&Status>) -> Json {
+    let mut fields = vec![("plugin", repo.into()), ("action", "status".into())];
+    match status {
+        None => fields.push(("state", "not_installed".into())),
+        Some(s) if s.commit.is_empty() => fields.push(("state", "not_git".into())),
+        Some(s) => {
+            fields.extend(vec![
+                ("state", "installed".into()),
+                ("branch", s.branch.clone().into()),
+                ("commit", s.commit.as_str().into()),
+                ("date", s.date.as_str().into()),
+                ("dirty", s.dirty.into()),
+                ("url", s.url.clone().into()),
+                ("url_matches", s.url_matches.into()),
+            ]);
+        }
+    }
+    Json::Object(fields)
+}
+
+/// Returns the object of an error of `kind`, which is of `plugin` if any.
+pub fn error_json(plugin: Option<&str>, kind: &str, error: &str) -> Json {
+    Json::Object(vec![
+        ("plugin", plugin.into()),
+        ("action", "error".into()),
+        ("kind", kind.into()),
+        ("error", error.into()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Commit;
+
+    #[test]
+    fn test_json() {
+        let s = Json::from("a \"b\" c\\d\ne\r\tf\u{1}\u{1f}g \u{e9}");
+        assert_eq!(s.to_string(), "\"a \\\"b\\\" c\\\\d\\ne\\r\\tf\\u0001\\u001fg \u{e9}\"");
+        let v = Json::Object(vec![
+            ("a", Json::Array(vec![Json::Null, true.into(), Json::Number(10)])),
+            ("b", Json::Array(vec![])),
+            ("c", Json::Object(vec![])),
+            ("d", None::<&str>.into()),
+            ("e", Some("x").into()),
+        ]);
+        assert_eq!(
+            v.to_string(),
+            "{\"a\":[null,true,10],\"b\":[],\"c\":{},\"d\":null,\"e\":\"x\"}"
+        );
+    }
+
+    #[test]
+    fn test_event_json() {
+        let e = Event::Updated("abc".to_owned(), "def".to_owned());
+        assert_eq!(
+            event_json("kana/vim-vspec", &e).to_string(),
+            "{\"plugin\":\"kana/vim-vspec\",\"action\":\"updated\",\"old\":\"abc\",\"new\":\"def\"}"
+        );
+        let e = Event::Repointed(None, "https://github.com/a/b".to_owned());
+        assert_eq!(
+            event_json("a/b", &e).to_string(),
+            "{\"plugin\":\"a/b\",\"action\":\"repointed\",\"old_url\":null,\
+             \"new_url\":\"https://github.com/a/b\"}"
+        );
+        let e = Event::Notice("Waiting...".to_owned());
+        assert_eq!(
+            event_json("/root", &e).to_string(),
+            "{\"path\":\"/root\",\"action\":\"notice\",\"message\":\"Waiting...\"}"
+        );
+    }
+
+    #[test]
+    fn test_change_json() {
+        let c = Change {
+            repo: "a/b".to_owned(),
+            before: "abc".to_owned(),
+            after: "def".to_owned(),
+            commits: vec![
+                Commit {
+                    id: "def".to_owned(),
+                    subject: "Drop \"x\"".to_owned(),
+                    breaking: true,
+                },
+            ],
+            recloned: false,
+        };
+        assert_eq!(
+            change_json(&c).to_string(),
+            "{\"plugin\":\"a/b\",\"action\":\"commits\",\"old\":\"abc\",\"new\":\"def\",\
+             \"recloned\":false,\"commits\":[{\"id\":\"def\",\"subject\":\"Drop \\\"x\\\"\",\
+             \"breaking\":true}]}"
+        );
+    }
+
+    #[test]
+    fn test_status_json() {
+        assert_eq!(
+            status_json("a/b", None).to_string(),
+            "{\"plugin\":\"a/b\",\"action\":\"status\",\"state\":\"not_installed\"}"
+        );
+        let mut s = Status {
+            branch: Some("master".to_owned()),
+            commit: "abc1234".to_owned(),
+            date: "2017-10-18".to_owned(),
+            dirty: true,
+            url: None,
+            url_matches: false,
+        };
+        assert_eq!(
+            status_json("a/b", Some(&s)).to_string(),
+            "{\"plugin\":\"a/b\",\"action\":\"status\",\"state\":\"installed\",\
+             \"branch\":\"master\",\"commit\":\"abc1234\",\"date\":\"2017-10-18\",\
+             \"dirty\":true,\"url\":null,\"url_matches\":false}"
+        );
+        s.commit.clear();
+        assert_eq!(
+            status_json("a/b", Some(&s)).to_string(),
+            "{\"plugin\":\"a/b\",\"action\":\"status\",\"state\":\"not_git\"}"
+        );
+    }
+
+    #[test]
+    fn test_error_json() {
+        assert_eq!(
+            error_json(Some("a/b"), "command", "a/b: clone failed\nfatal: \"x\"").to_string(),
+            "{\"plugin\":\"a/b\",\"action\":\"error\",\"kind\":\"command\",\
+             \"error\":\"a/b: clone failed\\nfatal: \\\"x\\\"\"}"
+        );
+        assert_eq!(
+            error_json(None, "usage", "no such flag: --x").to_string(),
+            "{\"plugin\":null,\"action\":\"error\",\"kind\":\"usage\",\
+             \"error\":\"no such flag: --x\"}"
+        );
+    }
+}
@@ -3,102 +3,996 @@
 #![feature(ascii_ctype)]
 #![feature(slice_patterns)]
 
+mod bundle;
+mod cache;
+mod changelog;
+mod config;
+mod event;
+mod git;
+mod glue;
+mod helptags;
+mod json;
+mod lock;
+mod lockfile;
 mod parse;
+mod snapshot;
+mod status;
+mod tap;
+#[cfg(test)]
+mod testing;
+mod vendor;
 mod version;
 
+pub use bundle::{Bundle, export_bundle, extract_bundle};
+pub use cache::{get_cache_path, remove_unused, unused};
+pub use changelog::{Change, Commit};
+pub use config::{Config, ConfigError, get_config_path};
+pub use event::{Event, Observer};
+pub use glue::{Script, glue};
+pub use helptags::helptags;
+pub use json::{Json, change_json, error_json, event_json, status_json};
+pub use lock::Lock;
+pub use lockfile::{apply_lockfile, lockfile};
 pub use parse::{Flavor, Parser, ParseError};
+pub use snapshot::{rollback, snapshot, snapshots};
+pub use status::{Outdated, Status, outdated, status};
+pub use tap::{Outcome, Spec, TestCase, junit, matches, parse_tap};
+pub use vendor::vendor;
 
 use std::env;
 use std::error::Error;
 use std::fmt;
-use std::io;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-/// Gets the default root directory.
-/// When succeeded in obtaining the home direcotry, returns `$HOME/.vim/rflavors`.
+/// Gets the default root directory for `target` and `layout`.
+/// When succeeded in obtaining the home direcotry, returns `$HOME/.vim/rflavors` for
+/// `Layout::Flat`, and `$HOME/.vim/pack/rflavors` for `Layout::Pack`.
+/// For Neovim, `$XDG_DATA_HOME/nvim/rflavors` and `$XDG_DATA_HOME/nvim/site/pack/rflavors`
+/// are returned respectively, where `XDG_DATA_HOME` defaults to `$HOME/.local/share`.
 /// Otherwise, returns None.
-pub fn get_root() -> Option<PathBuf> {
-    env::home_dir().map(|mut p| {
-        p.push(".vim");
+pub fn get_root(target: Target, layout: Layout) -> Option<Root> {
+    let base = match target {
+        Target::Vim => env::home_dir().map(|h| h.join(".vim")),
+        Target::Nvim => {
+            env::var_os("XDG_DATA_HOME")
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| env::home_dir().map(|h| h.join(".local").join("share")))
+                .map(|d| d.join("nvim"))
+        }
+    };
+    base.map(|mut p| {
+        if layout == Layout::Pack {
+            if target == Target::Nvim {
+                p.push("site");
+            }
+            p.push("pack");
+        }
         p.push("rflavors");
-        p
+        Root::new(p, layout)
     })
 }
 
-fn complete(s: &str) -> String {
-    match *s.split('/').collect::<Vec<&str>>() {
-        [vs] => format!("git://github.com/vim-scripts/{}.git", vs),
-        [u, r] => format!("git://github.com/{}/{}.git", u, r),
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The editor into which plugins are deployed.
+pub enum Target {
+    /// Vim.
+    Vim,
+    /// Neovim.
+    Nvim,
+}
+
+impl Target {
+    /// Returns the target named `name`, that is, "vim" or "nvim".
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "vim" => Some(Target::Vim),
+            "nvim" => Some(Target::Nvim),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the target.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Target::Vim => "vim",
+            Target::Nvim => "nvim",
+        }
+    }
+}
+
+/// Searches `dir` and its ancestors for a file named `name`, and returns the first one found.
+pub fn find_flavor_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    dir.ancestors().map(|d| d.join(name)).find(
+        |p| p.is_file(),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Describes how plugins are placed under a root directory.
+pub enum Layout {
+    /// Each plugin is placed directly under the root.
+    Flat,
+    /// The root is a native package of Vim 8 or Neovim; each plugin is placed under
+    /// `start` or, if it is optional, `opt`.
+    Pack,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A directory into which plugins are installed.
+pub struct Root {
+    path: PathBuf,
+    layout: Layout,
+}
+
+impl Root {
+    /// Creates a new `Root` placing plugins under `path` according to `layout`.
+    pub fn new<P: Into<PathBuf>>(path: P, layout: Layout) -> Root {
+        Root {
+            path: path.into(),
+            layout,
+        }
+    }
+
+    /// Returns the path of the root directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the layout of the root directory.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Returns the directory into which `f` is installed.
+    pub fn dir(&self, f: &Flavor) -> PathBuf {
+        let n = f.repo.replace(is_invalid, "_");
+        match self.layout {
+            Layout::Flat => self.path.join(n),
+            Layout::Pack if f.opt => self.path.join("opt").join(n),
+            Layout::Pack => self.path.join("start").join(n),
+        }
+    }
+
+    /// Returns the directories of all plugins under the root, whether declared or not.
+    /// Entries whose names start with '.' are reserved for vim-flavor itself.
+    pub fn dirs(&self) -> io::Result<Vec<PathBuf>> {
+        let mut dirs = Vec::new();
+        for p in self.parents() {
+            if !p.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(p)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() &&
+                    !entry.file_name().to_string_lossy().starts_with('.')
+                {
+                    dirs.push(entry.path());
+                }
+            }
+        }
+        dirs.sort();
+        Ok(dirs)
+    }
+
+    /// Returns the directories which contain plugins.
+    fn parents(&self) -> Vec<PathBuf> {
+        match self.layout {
+            Layout::Flat => vec![self.path.clone()],
+            Layout::Pack => vec![self.path.join("start"), self.path.join("opt")],
+        }
+    }
+}
+
+/// The prefix of temporary directories into which plugins are cloned.
+const TEMP_PREFIX: &str = ".tmp-";
+
+/// Returns the temporary sibling of the plugin directory `d`.
+fn temp_dir(d: &Path) -> PathBuf {
+    let name = d.file_name().expect("plugin directory has no name");
+    d.with_file_name(format!("{}{}", TEMP_PREFIX, name.to_string_lossy()))
+}
+
+/// Removes temporary directories left by interrupted clones under `root`.
+fn remove_temp_dirs(root: &Root, opts: &Options) -> io::Result<()> {
+    for p in root.parents() {
+        if !p.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(p)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+                let msg = format!("Removing an incomplete clone: {}", entry.path().display());
+                opts.notify(&root.path().to_string_lossy(), &Event::Notice(msg));
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the directories under `root` which no flavor in `fs` is installed into.
+pub fn orphans(fs: &[Flavor], root: &Root) -> io::Result<Vec<PathBuf>> {
+    let used: Vec<PathBuf> = fs.iter().map(|f| root.dir(f)).collect();
+    Ok(root.dirs()?.into_iter().filter(|d| !used.contains(d)).collect())
+}
+
+/// Hosts which shorthands like `gh:user/repo` refer to.
+const HOSTS: &[(&str, &str)] = &[
+    ("gh", "github.com"),
+    ("gitlab", "gitlab.com"),
+    ("bitbucket", "bitbucket.org"),
+    ("sr.ht", "git.sr.ht"),
+];
+
+fn complete(s: &str, opts: &Options) -> String {
+    let (host, path, prefixed) = match s.find(':') {
+        Some(n) => {
+            match HOSTS.iter().find(|&&(p, _)| p == &s[..n]) {
+                Some(&(_, host)) => (host, &s[n + 1..], true),
+                None => return s.to_owned(),
+            }
+        }
+        None => (opts.host.as_str(), s, false),
+    };
+    match *path.split('/').collect::<Vec<&str>>() {
+        [vs] if !prefixed => {
+            match opts.vim_scripts {
+                Some(ref u) => opts.protocol.url(host, &format!("{}/{}", u, vs)),
+                None => s.to_owned(),
+            }
+        }
+        [_, _] => opts.protocol.url(host, path),
         _ => s.to_owned(),
     }
 }
 
+/// Returns the URL to clone and fetch `repo` from, that is, the completed URL rewritten by
+/// the longest matching prefix in `opts.rewrites`.
+fn url(repo: &str, opts: &Options) -> String {
+    let url = complete(repo, opts);
+    match opts.rewrites
+        .iter()
+        .filter(|&&(ref prefix, _)| url.starts_with(prefix.as_str()))
+        .max_by_key(|&&(ref prefix, _)| prefix.len()) {
+        Some(&(ref prefix, ref to)) => format!("{}{}", to, &url[prefix.len()..]),
+        None => url,
+    }
+}
+
+/// Returns true if `a` and `b` refer to the same repository, that is, their URLs differ only in
+/// the protocol, the user name, the ".git" suffix, a trailing slash, or the case.
+pub fn same_repo(a: &str, b: &str, opts: &Options) -> bool {
+    repo_key(&url(a, opts)) == repo_key(&url(b, opts))
+}
+
+/// Returns `url` without what `same_repo` ignores, like "github.com/user/repo".
+fn repo_key(url: &str) -> String {
+    let mut s = url;
+    while s.ends_with('/') {
+        s = &s[..s.len() - 1];
+    }
+    if s.ends_with(".git") {
+        s = &s[..s.len() - 4];
+    }
+    if let Some(n) = s.find("://") {
+        s = &s[n + 3..];
+    }
+    let host_end = s.find('/').unwrap_or(s.len());
+    if let Some(n) = s[..host_end].find('@') {
+        s = &s[n + 1..];
+    }
+    // An scp-like URL separates the host and the path with ':'.
+    match s.find(':') {
+        Some(n) if n < s.find('/').unwrap_or(s.len()) => {
+            format!("{}/{}", &s[..n], &s[n + 1..]).to_lowercase()
+        }
+        _ => s.to_lowercase(),
+    }
+}
+
 fn is_invalid(ch: char) -> bool {
     !ch.is_alphanumeric() && ch != '-' && ch != '_' && ch != '.'
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A protocol to clone repositories which are written in shorthand.
+pub enum Protocol {
+    /// `https://host/user/repo.git`.
+    Https,
+    /// `git@host:user/repo.git`.
+    Ssh,
+}
+
+impl Protocol {
+    /// Returns the protocol named `name`, that is, "https" or "ssh".
+    pub fn from_name(name: &str) -> Option<Protocol> {
+        match name {
+            "https" => Some(Protocol::Https),
+            "ssh" => Some(Protocol::Ssh),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the protocol.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Protocol::Https => "https",
+            Protocol::Ssh => "ssh",
+        }
+    }
+
+    fn url(&self, host: &str, path: &str) -> String {
+        // SourceHut names users with '~' and does not accept the ".git" suffix.
+        let (path, suffix) = match host {
+            "git.sr.ht" if path.starts_with('~') => (path.to_owned(), ""),
+            "git.sr.ht" => (format!("~{}", path), ""),
+            _ => (path.to_owned(), ".git"),
+        };
+        match *self {
+            Protocol::Https => format!("https://{}/{}{}", host, path, suffix),
+            Protocol::Ssh => format!("git@{}:{}{}", host, path, suffix),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Options for installing and updating plugins.
+pub struct Options {
+    /// Re-clone a plugin instead of re-pointing its remote when its URL has changed.
+    pub force: bool,
+    /// The protocol for repositories written in shorthand, like `user/repo`.
+    pub protocol: Protocol,
+    /// The host for repositories written in shorthand without a prefix like `gh:`.
+    pub host: String,
+    /// The user who hosts a repository written as a single name, like `vspec`.
+    /// If None, a single name is used as is.
+    pub vim_scripts: Option<String>,
+    /// Pairs of a URL prefix and its replacement, like `url.<base>.insteadOf` of git.
+    pub rewrites: Vec<(String, String)>,
+    /// The depth of clones. If None, plugins are cloned with full history.
+    pub depth: Option<u32>,
+    /// The number of plugins installed or updated at a time.
+    pub jobs: usize,
+    /// Commands run in the directory of each plugin.
+    pub hooks: Hooks,
+    /// The directory of the cache of bare repositories shared among roots. If given, plugins
+    /// are fetched into the cache first, and then cloned or fetched from it.
+    pub cache: Option<PathBuf>,
+    /// If true, plugins are installed only from the cache or repositories on the file system.
+    pub offline: bool,
+    /// If true, `install` and `update` wait for another process holding the lock on the root;
+    /// otherwise, they fail immediately.
+    pub wait: bool,
+    /// Receives the events of `install` and `update`. If None, events are discarded.
+    pub observer: Option<Arc<Observer>>,
+}
+
+impl Options {
+    fn notify(&self, name: &str, event: &Event) {
+        if let Some(ref o) = self.observer {
+            o.notify(name, event);
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            force: false,
+            protocol: Protocol::Https,
+            host: "github.com".to_owned(),
+            vim_scripts: Some("vim-scripts".to_owned()),
+            rewrites: vec![],
+            depth: Some(1),
+            jobs: 1,
+            hooks: Hooks::default(),
+            wait: true,
+            cache: None,
+            offline: false,
+            observer: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Shell commands run in the directory of a plugin.
+pub struct Hooks {
+    /// Run after a plugin is cloned.
+    pub post_install: Option<String>,
+    /// Run after a plugin is updated to another commit.
+    pub post_update: Option<String>,
+}
+
 /// Parses content of the flavor file and installs plugins which are described in it.
-pub fn install(fs: &[Flavor], root: &Path) -> Result<(), InstallError> {
-    for f in fs {
-        let n = f.repo.replace(is_invalid, "_");
-        let d = root.join(n);
+/// An installed plugin whose remote differs from the flavor file is re-fetched from the
+/// new remote, or re-cloned if `opts.force` is true.
+/// Plugins are cloned into temporary directories and moved into place only on success;
+/// temporary directories left by interrupted runs are removed first.
+/// A plugin with `commit` is reset to the commit. In offline mode, fails without changing
+/// anything unless every plugin and commit is available locally.
+/// A failure of a plugin does not stop the others, and every failure is returned.
+/// The root is locked while installing. Events are sent to `opts.observer`.
+pub fn install(fs: &[Flavor], root: &Root, opts: &Options) -> Result<(), InstallError> {
+    let _lock = Lock::acquire(root, opts)?;
+    remove_temp_dirs(root, opts)?;
+    if opts.offline {
+        let missing = missing(fs, root, opts)?;
+        if !missing.is_empty() {
+            return Err(InstallError::Missing(missing));
+        }
+    }
+    let results = parallel(fs, opts.jobs, |f| {
+        observe(f, opts, || install_flavor(f, &root.dir(f), opts))
+    });
+    let helptags = generate_helptags(fs, &results, root);
+    collect(results)?;
+    helptags
+}
+
+fn install_flavor(f: &Flavor, d: &Path, opts: &Options) -> Result<(), InstallError> {
+    if !d.join(".git").exists() {
         if d.exists() {
-            continue;
+            return Ok(());
         }
-        let dest = d.to_str().expect(
-            "failed to build destination path for 'git clone'",
-        );
-        let r = complete(&f.repo);
-        let output = Command::new("git")
-            .args(&["clone", "--depth", "1", "--branch", &f.branch, &r, dest])
-            .output()?;
-        if !output.status.success() {
-            eprintln!(
-                "{}: failed to install:\n\
-                 {}",
-                f.repo,
-                String::from_utf8_lossy(&output.stderr)
-            );
-            return Err(InstallError::Exit(output.status));
+        clone(f, d, opts)?;
+        return report(f, d, None, opts);
+    }
+    let before = git::read(d, &["rev-parse", "HEAD"])?;
+    let repointed = repoint(f, d, opts)?;
+    let head = git::read(d, &["rev-parse", "HEAD"])?;
+    let locked = f.commit.as_ref().filter(|&c| head.as_ref() != Some(c));
+    let fetch = match locked {
+        Some(c) => !has_commit(d, c)?,
+        None => false,
+    };
+    let source = if repointed || fetch {
+        Some(obtain(f, opts)?)
+    } else {
+        None
+    };
+    if repointed {
+        update_flavor(f, d, source.as_ref().unwrap(), opts)?;
+    }
+    if let Some(c) = locked {
+        reset(f, d, c, source.as_ref(), opts)?;
+    }
+    report(f, d, before, opts)
+}
+
+/// Returns the values of `results`, or their errors: the error itself if only one flavor
+/// failed, and `InstallError::Many` if more did.
+fn collect<T>(results: Vec<Result<T, InstallError>>) -> Result<Vec<T>, InstallError> {
+    let mut values = Vec::new();
+    let mut errors = Vec::new();
+    for r in results {
+        match r {
+            Ok(v) => values.push(v),
+            Err(e) => errors.push(e),
         }
     }
-    Ok(())
+    match errors.len() {
+        0 => Ok(values),
+        1 => Err(errors.remove(0)),
+        _ => Err(InstallError::Many(errors)),
+    }
 }
 
-/// Parses content of the flavor file and updates plugins which are described in it.
-pub fn update(fs: &[Flavor], root: &Path) -> Result<(), InstallError> {
-    git_with_flavor(fs, root, false, |f, _| vec!["pull", "origin", &f.branch])
+/// Runs `fun` for `f`, and notifies the observer in `opts` that it is started and if it fails.
+fn observe<T, F>(f: &Flavor, opts: &Options, fun: F) -> Result<T, InstallError>
+where
+    F: FnOnce() -> Result<T, InstallError>,
+{
+    opts.notify(&f.repo, &Event::Started);
+    fun().map_err(|e| {
+        opts.notify(&f.repo, &Event::Failed(e.to_string()));
+        e
+    })
 }
 
-fn git_with_flavor<'a, 'b>(
-    fs: &'a [Flavor],
-    root: &Path,
-    not: bool,
-    args: fn(&'a Flavor, &str) -> Vec<&'b str>,
+/// Notifies the observer in `opts` of the commit checked out in `d`, which was `before`.
+fn report(
+    f: &Flavor,
+    d: &Path,
+    before: Option<String>,
+    opts: &Options,
 ) -> Result<(), InstallError> {
+    let after = git::read(d, &["rev-parse", "HEAD"])?.unwrap_or_default();
+    let event = match before {
+        None => Event::Installed(after),
+        Some(ref b) if *b == after => Event::UpToDate(after),
+        Some(b) => Event::Updated(b, after),
+    };
+    opts.notify(&f.repo, &event);
+    Ok(())
+}
+
+/// Where the objects of a plugin are fetched from.
+enum Source {
+    /// The remote repository.
+    Remote(String),
+    /// A local repository, that is, a mirror in the cache or a repository on the file system.
+    /// A mirror is locked until the source is dropped, so that it is not removed while used.
+    Local(PathBuf, Option<Lock>),
+}
+
+impl Source {
+    fn as_str(&self) -> &str {
+        match *self {
+            Source::Remote(ref url) => url,
+            Source::Local(ref path, _) => {
+                path.to_str().expect("failed to build path for the source")
+            }
+        }
+    }
+}
+
+/// Obtains the objects of `f`, and returns where to clone or fetch them from.
+/// If the cache is enabled, the objects are fetched into it first. In offline mode,
+/// returns a local repository without fetching anything.
+fn obtain(f: &Flavor, opts: &Options) -> Result<Source, InstallError> {
+    let url = url(&f.repo, opts);
+    if opts.offline {
+        let not_found = || {
+            InstallError::Missing(vec![format!("{}: {} is not in the cache", f.repo, url)])
+        };
+        let path = local_source(&url, opts).ok_or_else(&not_found)?;
+        let lock = match opts.cache {
+            Some(ref cache) if path == cache::entry(cache, &url) => {
+                Some(cache::lock(cache, &url, opts)?)
+            }
+            _ => None,
+        };
+        // The mirror may have been removed before it was locked.
+        if !path.exists() {
+            return Err(not_found());
+        }
+        return Ok(Source::Local(path, lock));
+    }
+    match opts.cache {
+        Some(ref cache) => {
+            opts.notify(&f.repo, &Event::Fetching(url.clone()));
+            let (path, lock) = cache::mirror(&f.repo, cache, &url, opts)?;
+            Ok(Source::Local(path, Some(lock)))
+        }
+        None => Ok(Source::Remote(url)),
+    }
+}
+
+/// Returns the mirror of `url` in the cache if any, or `url` itself if it is on the file system.
+fn local_source(url: &str, opts: &Options) -> Option<PathBuf> {
+    if let Some(ref cache) = opts.cache {
+        let m = cache::entry(cache, url);
+        if m.is_dir() {
+            return Some(m);
+        }
+    }
+    let path = Path::new(if url.starts_with("file://") {
+        &url["file://".len()..]
+    } else {
+        url
+    });
+    if path.is_absolute() && path.exists() {
+        Some(path.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Lists what is needed to install `fs` but is not available locally.
+fn missing(fs: &[Flavor], root: &Root, opts: &Options) -> Result<Vec<String>, InstallError> {
+    let mut missing = Vec::new();
     for f in fs {
-        let n = f.repo.replace(is_invalid, "_");
-        let d = root.join(n);
-        if not == d.exists() {
-            eprintln!("Skipped {}: not installed yet.", f.repo);
+        let d = root.dir(f);
+        let url = url(&f.repo, opts);
+        let installed = d.join(".git").exists();
+        let has_locked = match f.commit {
+            Some(ref c) if installed => has_commit(&d, c)?,
+            Some(_) => false,
+            None => true,
+        };
+        if installed && has_locked &&
+            git::read(&d, &["config", "--get", "remote.origin.url"])?.as_ref() == Some(&url)
+        {
             continue;
         }
-        let dest = d.to_str().expect(
-            "failed to build destination path for 'git pull'",
-        );
-        let output = Command::new("git")
-            .current_dir(dest)
-            .args(args(f, dest))
-            .output()?;
-        if !output.status.success() {
-            eprintln!("{}:", f.repo);
-            eprintln!("{}", String::from_utf8_lossy(&output.stderr));
-            return Err(InstallError::Exit(output.status));
+        let source = match local_source(&url, opts) {
+            Some(source) => source,
+            None => {
+                missing.push(format!("{}: {} is not in the cache", f.repo, url));
+                continue;
+            }
+        };
+        missing.extend(missing_branch(f, &source)?);
+        if let Some(ref c) = f.commit {
+            if !has_locked && !has_commit(&source, c)? {
+                missing.push(format!("{}: commit {} is not in {}", f.repo, c, source.display()));
+            }
         }
     }
+    Ok(missing)
+}
+
+/// Lists what is needed to update the installed plugins of `fs` but is not available locally.
+fn missing_updates(
+    fs: &[Flavor],
+    root: &Root,
+    opts: &Options,
+) -> Result<Vec<String>, InstallError> {
+    let mut missing = Vec::new();
+    for f in fs.iter().filter(|f| root.dir(f).exists()) {
+        let url = url(&f.repo, opts);
+        match local_source(&url, opts) {
+            Some(source) => missing.extend(missing_branch(f, &source)?),
+            None => missing.push(format!("{}: {} is not in the cache", f.repo, url)),
+        }
+    }
+    Ok(missing)
+}
+
+/// Reports the branch or tag of `f` if it is not in the repository `source`.
+fn missing_branch(f: &Flavor, source: &Path) -> io::Result<Option<String>> {
+    let branch = format!("{}^{{commit}}", f.branch);
+    if git::read(source, &["rev-parse", "--verify", "--quiet", &branch])?.is_some() {
+        return Ok(None);
+    }
+    Ok(Some(format!("{}: {} is not in {}", f.repo, f.branch, source.display())))
+}
+
+fn has_commit(dir: &Path, commit: &str) -> io::Result<bool> {
+    let object = format!("{}^{{commit}}", commit);
+    Ok(git::read(dir, &["cat-file", "-e", &object])?.is_some())
+}
+
+/// Resets the checkout `d` to `commit`, fetching it from `source` if missing.
+fn reset(
+    f: &Flavor,
+    d: &Path,
+    commit: &str,
+    source: Option<&Source>,
+    opts: &Options,
+) -> Result<(), InstallError> {
+    if let Some(source) = source {
+        if !has_commit(d, commit)? {
+            let mut args = vec!["fetch", "--quiet"];
+            if let Source::Remote(_) = *source {
+                args.extend(&["--depth", "1"]);
+            }
+            args.extend(&[source.as_str(), commit]);
+            opts.notify(&f.repo, &Event::Fetching(source.as_str().to_owned()));
+            git::exec_progress(&f.repo, Operation::Fetch, d, &args, opts)?;
+        }
+    }
+    git::exec(&f.repo, Operation::Checkout, d, &["reset", "--quiet", "--hard", commit])
+}
+
+/// Applies `fun` to each flavor in `fs` with up to `jobs` threads, and returns the results
+/// in the order of `fs`. A failure of a flavor does not stop the others.
+fn parallel<T, F>(fs: &[Flavor], jobs: usize, fun: F) -> Vec<Result<T, InstallError>>
+where
+    T: Send,
+    F: Fn(&Flavor) -> Result<T, InstallError> + Sync,
+{
+    if jobs <= 1 {
+        return fs.iter().map(|f| fun(f)).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|s| for _ in 0..jobs {
+        s.spawn(|| loop {
+            let i = next.fetch_add(1, Ordering::SeqCst);
+            if i >= fs.len() {
+                break;
+            }
+            let r = fun(&fs[i]);
+            results.lock().unwrap().push((i, r));
+        });
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Clones `f` into a temporary directory, replaces `d` with it, and runs the post-install hook.
+/// `d` is left untouched if the clone fails.
+fn clone(f: &Flavor, d: &Path, opts: &Options) -> Result<(), InstallError> {
+    let tmp = temp_dir(d);
+    if tmp.exists() {
+        fs::remove_dir_all(&tmp)?;
+    }
+    let dest = tmp.to_str().expect(
+        "failed to build destination path for 'git clone'",
+    );
+    let r = url(&f.repo, opts);
+    let source = obtain(f, opts)?;
+    let depth = opts.depth.map(|n| n.to_string());
+    let mut args = vec!["clone"];
+    // A local clone shares objects by hard links, so it need not be shallow.
+    if let (&Source::Remote(_), Some(ref depth)) = (&source, &depth) {
+        args.extend(&["--depth", depth]);
+    }
+    args.extend(&["--branch", &f.branch, source.as_str(), dest]);
+    opts.notify(&f.repo, &Event::Cloning(source.as_str().to_owned()));
+    if let Err(e) = git::exec_progress(&f.repo, Operation::Clone, Path::new("."), &args, opts) {
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        return Err(e);
+    }
+    if let Source::Local(..) = source {
+        git::exec(&f.repo, Operation::Remote, &tmp, &["remote", "set-url", "origin", &r])?;
+    }
+    if let Some(ref c) = f.commit {
+        if let Err(e) = reset(f, &tmp, c, Some(&source), opts) {
+            fs::remove_dir_all(&tmp)?;
+            return Err(e);
+        }
+    }
+    if d.exists() {
+        fs::remove_dir_all(d)?;
+    }
+    fs::rename(&tmp, d)?;
+    run_hook(f, d, &opts.hooks.post_install, opts)
+}
+
+/// Runs `hook` in `d`, sending each line of its output to the observer in `opts`.
+fn run_hook(
+    f: &Flavor,
+    d: &Path,
+    hook: &Option<String>,
+    opts: &Options,
+) -> Result<(), InstallError> {
+    let cmd = match *hook {
+        Some(ref cmd) => cmd,
+        None => return Ok(()),
+    };
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .current_dir(d)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout of a hook is not piped");
+    let stderr = child.stderr.take().expect("stderr of a hook is not piped");
+    let errors = thread::scope(|s| {
+        let errors = s.spawn(|| -> io::Result<Vec<u8>> {
+            let mut errors = Vec::new();
+            for l in BufReader::new(stderr).lines() {
+                let l = l?;
+                opts.notify(&f.repo, &Event::HookOutput(l.clone()));
+                errors.extend(l.as_bytes());
+                errors.push(b'\n');
+            }
+            Ok(errors)
+        });
+        for l in BufReader::new(stdout).lines() {
+            opts.notify(&f.repo, &Event::HookOutput(l?));
+        }
+        errors.join().expect("a thread reading a hook panicked")
+    })?;
+    let output = Output {
+        status: child.wait()?,
+        stdout: Vec::new(),
+        stderr: errors,
+    };
+    if !output.status.success() {
+        let args = ["-c", cmd.as_str()];
+        return Err(CommandError::new(&f.repo, Operation::Hook, "sh", &args, &output).into());
+    }
     Ok(())
 }
+
+/// Makes the remote "origin" of the checkout `d` point to the URL of `f`, re-cloning it if
+/// `opts.force` is true. Returns true if the remote is re-pointed and needs to be fetched.
+fn repoint(f: &Flavor, d: &Path, opts: &Options) -> Result<bool, InstallError> {
+    let url = url(&f.repo, opts);
+    let origin = git::read(d, &["config", "--get", "remote.origin.url"])?;
+    if origin.as_ref() == Some(&url) {
+        return Ok(false);
+    }
+    opts.notify(&f.repo, &Event::Repointed(origin.clone(), url.clone()));
+    if opts.force {
+        clone(f, d, opts)?;
+        return Ok(false);
+    }
+    let cmd = if origin.is_some() { "set-url" } else { "add" };
+    git::exec(&f.repo, Operation::Remote, d, &["remote", cmd, "origin", &url])?;
+    Ok(true)
+}
+
+/// Parses content of the flavor file and updates plugins which are described in it.
+/// Each plugin is reset to the commit which its branch or tag points to on the remote,
+/// even if the branch has changed in the flavor file or has been force-pushed.
+/// Before updating, takes a snapshot of the checked-out commits for `rollback`.
+/// A plugin whose remote differs from the flavor file is handled as `install` does.
+/// `commit` of each flavor is ignored.
+/// The root is locked while updating. Returns the commits pulled into each plugin.
+/// In offline mode, fails without changing anything unless the branch or tag of every
+/// installed plugin is available locally. Failures are handled as `install` does.
+pub fn update(fs: &[Flavor], root: &Root, opts: &Options) -> Result<Vec<Change>, InstallError> {
+    let _lock = Lock::acquire(root, opts)?;
+    remove_temp_dirs(root, opts)?;
+    if opts.offline {
+        let missing = missing_updates(fs, root, opts)?;
+        if !missing.is_empty() {
+            return Err(InstallError::Missing(missing));
+        }
+    }
+    snapshot(fs, root)?;
+    let results = parallel(fs, opts.jobs, |f| {
+        let d = root.dir(f);
+        if !d.exists() {
+            opts.notify(&f.repo, &Event::Skipped);
+            return Ok(None);
+        }
+        observe(f, opts, || {
+            let before = git::read(&d, &["rev-parse", "HEAD"])?.unwrap_or_default();
+            repoint(f, &d, opts)?;
+            update_flavor(f, &d, &obtain(f, opts)?, opts)?;
+            let after = git::read(&d, &["rev-parse", "HEAD"])?.unwrap_or_default();
+            report(f, &d, Some(before.clone()), opts)?;
+            if before == after {
+                return Ok(None);
+            }
+            run_hook(f, &d, &opts.hooks.post_update, opts)?;
+            // A plugin re-cloned from another URL may not have the previous commit.
+            let recloned = !has_commit(&d, &before)?;
+            let commits = if recloned {
+                vec![]
+            } else {
+                changelog::commits(&d, &before, &after)?
+            };
+            Ok(Some(Change {
+                repo: f.repo.clone(),
+                before,
+                after,
+                commits,
+                recloned,
+            }))
+        })
+    });
+    let helptags = generate_helptags(fs, &results, root);
+    let changes = collect(results)?.into_iter().filter_map(|c| c).collect();
+    helptags?;
+    Ok(changes)
+}
+
+/// Fetches the branch or tag of `f` from `source`, and checks it out.
+fn update_flavor(
+    f: &Flavor,
+    d: &Path,
+    source: &Source,
+    opts: &Options,
+) -> Result<(), InstallError> {
+    opts.notify(&f.repo, &Event::Fetching(source.as_str().to_owned()));
+    let args = ["fetch", "--quiet", source.as_str(), &f.branch];
+    git::exec_progress(&f.repo, Operation::Fetch, d, &args, opts)?;
+    let modified = git::read(d, &["status", "--porcelain", "--untracked-files=no"])?;
+    if modified.map_or(false, |s| !s.is_empty()) {
+        opts.notify(&f.repo, &Event::Discarding);
+    }
+    let fetched = fs::read_to_string(d.join(".git").join("FETCH_HEAD"))?;
+    let args: &[&str] = if fetched.contains("\tbranch '") {
+        &["checkout", "--quiet", "--force", "-B", &f.branch, "FETCH_HEAD"]
+    } else {
+        &["checkout", "--quiet", "--force", "--detach", "FETCH_HEAD"]
+    };
+    git::exec(&f.repo, Operation::Checkout, d, args)
+}
+
+/// Generates the tags of the flavors in `fs` whose `results` are successful, so that a failure
+/// of a plugin does not leave the others without them.
+fn generate_helptags<T>(
+    fs: &[Flavor],
+    results: &[Result<T, InstallError>],
+    root: &Root,
+) -> Result<(), InstallError> {
+    for (f, _) in fs.iter().zip(results).filter(|&(_, r)| r.is_ok()) {
+        let doc = root.dir(f).join("doc");
+        if doc.is_dir() {
+            helptags(&doc)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// An operation which runs a command for a plugin.
+pub enum Operation {
+    /// Cloning a plugin, or creating a repository in the cache or a bundle.
+    Clone,
+    /// Fetching commits, or listing the branches and tags of a remote.
+    Fetch,
+    /// Checking out or resetting to a commit.
+    Checkout,
+    /// Adding or changing the remote of a checkout.
+    Remote,
+    /// Running a hook in the configuration file.
+    Hook,
+    /// Writing or extracting the archive of a bundle.
+    Archive,
+}
+
+impl Operation {
+    /// Returns the name of the operation in lowercase.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Operation::Clone => "clone",
+            Operation::Fetch => "fetch",
+            Operation::Checkout => "checkout",
+            Operation::Remote => "remote",
+            Operation::Hook => "hook",
+            Operation::Archive => "archive",
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A command which exited unsuccessfully while operating on a plugin.
+pub struct CommandError {
+    /// The repository of the flavor, or the path of the archive for `Operation::Archive`.
+    pub flavor: String,
+    /// What the command was run for.
+    pub operation: Operation,
+    /// The command and its arguments.
+    pub argv: Vec<String>,
+    /// The exit status of the command.
+    pub status: ExitStatus,
+    /// The standard error of the command.
+    pub stderr: String,
+}
+
+impl CommandError {
+    fn new(
+        flavor: &str,
+        operation: Operation,
+        program: &str,
+        args: &[&str],
+        output: &Output,
+    ) -> CommandError {
+        let mut argv = vec![program.to_owned()];
+        argv.extend(args.iter().map(|&a| a.to_owned()));
+        CommandError {
+            flavor: flavor.to_owned(),
+            operation,
+            argv,
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} failed: '{}' {}",
+            self.flavor,
+            self.operation.name(),
+            self.argv.join(" "),
+            self.status
+        )?;
+        if !self.stderr.is_empty() {
+            write!(f, "\n{}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for CommandError {
+    fn description(&self) -> &str {
+        "command failed"
+    }
+}
+
 #[derive(Debug)]
 /// Represents an error while installing plugins.
 pub enum InstallError {
@@ -106,8 +1000,12 @@ pub enum InstallError {
     IO(io::Error),
     /// Given Flavor file cannot be parsed successfully.
     Parse(ParseError),
-    /// Command exited with the exit status.
-    Exit(ExitStatus),
+    /// Command for a plugin exited unsuccessfully.
+    Command(CommandError),
+    /// Repositories or commits which are not available in offline mode.
+    Missing(Vec<String>),
+    /// Errors of more than one plugin, in the order of the plugins.
+    Many(Vec<InstallError>),
 }
 
 impl fmt::Display for InstallError {
@@ -115,7 +1013,21 @@ impl fmt::Display for InstallError {
         match *self {
             InstallError::IO(ref e) => write!(f, "IO error: {}", e),
             InstallError::Parse(ref e) => write!(f, "parse error: {}", e),
-            InstallError::Exit(status) => status.fmt(f),
+            InstallError::Command(ref e) => e.fmt(f),
+            InstallError::Missing(ref missing) => {
+                write!(f, "not available offline:")?;
+                for m in missing {
+                    write!(f, "\n  {}", m)?;
+                }
+                Ok(())
+            }
+            InstallError::Many(ref errors) => {
+                write!(f, "{} plugins failed:", errors.len())?;
+                for e in errors {
+                    write!(f, "\n{}", e)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -125,7 +1037,9 @@ impl Error for InstallError {
         match *self {
             InstallError::IO(ref e) => e.description(),
             InstallError::Parse(ref e) => e.description(),
-            InstallError::Exit(_) => "command exited",
+            InstallError::Command(_) => "command failed",
+            InstallError::Missing(_) => "not available offline",
+            InstallError::Many(_) => "plugins failed",
         }
     }
 
@@ -133,11 +1047,19 @@ impl Error for InstallError {
         match *self {
             InstallError::IO(ref e) => e.cause(),
             InstallError::Parse(ref e) => e.cause(),
-            InstallError::Exit(_) => None,
+            InstallError::Command(_) => None,
+            InstallError::Missing(_) => None,
+            InstallError::Many(_) => None,
         }
     }
 }
 
+impl From<CommandError> for InstallError {
+    fn from(e: CommandError) -> InstallError {
+        InstallError::Command(e)
+    }
+}
+
 impl From<ParseError> for InstallError {
     fn from(e: ParseError) -> InstallError {
         InstallError::Parse(e)
@@ -157,17 +1079,287 @@ mod tests {
 
     #[test]
     fn test_complete() {
+        let opts = Options::default();
+
         let s = "vspec";
-        let rs = complete(s);
-        assert_eq!(rs, "git://github.com/vim-scripts/vspec.git".to_owned());
+        let rs = complete(s, &opts);
+        assert_eq!(rs, "https://github.com/vim-scripts/vspec.git".to_owned());
 
         let s = "elpinal/vim-goyacc";
-        let rs = complete(s);
-        assert_eq!(rs, "git://github.com/elpinal/vim-goyacc.git".to_owned());
+        let rs = complete(s, &opts);
+        assert_eq!(rs, "https://github.com/elpinal/vim-goyacc.git".to_owned());
 
         let s = "https://github.com/elpinal/vim-goyacc";
-        let rs = complete(s);
+        let rs = complete(s, &opts);
         assert_eq!(rs, s.to_owned());
+
+        let s = "git@github.com:elpinal/vim-goyacc.git";
+        let rs = complete(s, &opts);
+        assert_eq!(rs, s.to_owned());
+
+        let s = "gitlab:user/repo";
+        let rs = complete(s, &opts);
+        assert_eq!(rs, "https://gitlab.com/user/repo.git".to_owned());
+
+        let s = "sr.ht:~user/repo";
+        let rs = complete(s, &opts);
+        assert_eq!(rs, "https://git.sr.ht/~user/repo".to_owned());
+
+        let s = "gh:vspec";
+        let rs = complete(s, &opts);
+        assert_eq!(rs, s.to_owned());
+
+        let opts = Options {
+            protocol: Protocol::Ssh,
+            host: "example.com".to_owned(),
+            vim_scripts: None,
+            ..Options::default()
+        };
+
+        let s = "vspec";
+        let rs = complete(s, &opts);
+        assert_eq!(rs, s.to_owned());
+
+        let s = "elpinal/vim-goyacc";
+        let rs = complete(s, &opts);
+        assert_eq!(rs, "git@example.com:elpinal/vim-goyacc.git".to_owned());
+
+        let s = "bitbucket:user/repo";
+        let rs = complete(s, &opts);
+        assert_eq!(rs, "git@bitbucket.org:user/repo.git".to_owned());
+
+        let s = "sr.ht:user/repo";
+        let rs = complete(s, &opts);
+        assert_eq!(rs, "git@git.sr.ht:~user/repo".to_owned());
+    }
+
+    #[test]
+    fn test_url() {
+        let opts = Options {
+            rewrites: vec![
+                ("https://".to_owned(), "git://".to_owned()),
+                ("https://github.com/".to_owned(), "file:///srv/".to_owned()),
+            ],
+            ..Options::default()
+        };
+        assert_eq!(url("user/repo", &opts), "file:///srv/user/repo.git");
+        assert_eq!(url("gitlab:user/repo", &opts), "git://gitlab.com/user/repo.git");
+        assert_eq!(url("/path/to/repo", &opts), "/path/to/repo");
+    }
+
+    #[test]
+    fn test_same_repo() {
+        let opts = Options::default();
+        for s in &[
+            "kana/vim-vspec",
+            "gh:kana/vim-vspec",
+            "https://github.com/kana/vim-vspec",
+            "https://github.com/kana/vim-vspec.git/",
+            "git@github.com:kana/vim-vspec.git",
+            "ssh://git@github.com/Kana/vim-vspec",
+        ] {
+            assert!(same_repo(s, "kana/vim-vspec", &opts), "{}", s);
+        }
+        assert!(!same_repo("kana/vim-vspec-matchers", "kana/vim-vspec", &opts));
+        assert!(!same_repo("gitlab:kana/vim-vspec", "kana/vim-vspec", &opts));
+        let opts = Options {
+            protocol: Protocol::Ssh,
+            ..Options::default()
+        };
+        assert!(same_repo("https://github.com/kana/vim-vspec", "kana/vim-vspec", &opts));
+    }
+
+    #[test]
+    fn test_install_mirror() {
+        let dir = testing::temp_dir("rust-vim-flavor-install-mirror-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let mirror = dir.join("mirror");
+        fs::create_dir(&mirror).unwrap();
+        testing::git(&mirror, &["clone", "-q", "--bare", repo.to_str().unwrap(), "user/repo.git"]);
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let opts = Options {
+            rewrites: vec![
+                (
+                    "https://github.com/".to_owned(),
+                    format!("file://{}/", mirror.display()),
+                ),
+            ],
+            ..Options::default()
+        };
+
+        let r = install(&[Flavor::new("user/repo")], &root, &opts);
+        let installed = root.path().join("user_repo").join("plugin/a.vim").exists();
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert!(r.is_ok());
+        assert!(installed);
+    }
+
+    #[test]
+    fn test_parallel() {
+        let fs: Vec<Flavor> = (0..10).map(|n| Flavor::new(&n.to_string())).collect();
+        let rs = parallel(&fs, 4, |f| if f.repo == "3" {
+            Err(InstallError::IO(io::Error::new(io::ErrorKind::Other, "3")))
+        } else {
+            Ok(f.repo.clone())
+        });
+        assert_eq!(rs.len(), 10);
+        assert_eq!(rs[2].as_ref().ok(), Some(&"2".to_owned()));
+        assert!(rs[3].is_err());
+        assert_eq!(rs[9].as_ref().ok(), Some(&"9".to_owned()));
+
+        let rs = parallel(&fs, 1, |f| if f.repo == "3" {
+            Err(InstallError::IO(io::Error::new(io::ErrorKind::Other, "3")))
+        } else {
+            Ok(f.repo.clone())
+        });
+        // A single job goes on after a failure as well.
+        assert_eq!(rs.len(), 10);
+        assert!(rs[3].is_err());
+        assert_eq!(rs[9].as_ref().ok(), Some(&"9".to_owned()));
+    }
+
+    #[test]
+    fn test_collect() {
+        let error = |m: &str| InstallError::IO(io::Error::new(io::ErrorKind::Other, m));
+        assert_eq!(collect(vec![Ok(1), Ok(2)]).ok(), Some(vec![1, 2]));
+        match collect(vec![Ok(1), Err(error("a"))]) {
+            Err(InstallError::IO(e)) => assert_eq!(e.to_string(), "a"),
+            r => panic!("unexpected {:?}", r),
+        }
+        match collect(vec![Err(error("a")), Ok(1), Err(error("b"))]) {
+            Err(e @ InstallError::Many(_)) => {
+                assert_eq!(e.to_string(), "2 plugins failed:\nIO error: a\nIO error: b")
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        let dir = testing::temp_dir("rust-vim-flavor-hooks-test");
+        let repos: Vec<PathBuf> = (0..3).map(|n| dir.join(format!("plugin{}", n))).collect();
+        for r in &repos {
+            testing::repo(r);
+        }
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let fs: Vec<Flavor> = repos.iter().map(|r| Flavor::new(r.to_str().unwrap())).collect();
+        let opts = Options {
+            depth: None,
+            jobs: 2,
+            hooks: Hooks {
+                post_install: Some("touch installed".to_owned()),
+                post_update: Some("touch updated".to_owned()),
+            },
+            ..Options::default()
+        };
+
+        let r1 = install(&fs, &root, &opts);
+        testing::commit(&repos[1], "plugin/a.vim", "Second commit");
+        let r2 = update(&fs, &root, &opts);
+        let failing = Options {
+            hooks: Hooks {
+                post_install: Some("echo oops >&2; exit 3".to_owned()),
+                post_update: None,
+            },
+            ..Options::default()
+        };
+        let r3 = install(&fs[..1], &Root::new(dir.join("failing"), Layout::Flat), &failing);
+        let installed: Vec<bool> = fs
+            .iter()
+            .map(|f| root.dir(f).join("installed").exists())
+            .collect();
+        let updated: Vec<bool> = fs.iter().map(|f| root.dir(f).join("updated").exists()).collect();
+        let shallow = root.dir(&fs[0]).join(".git").join("shallow").exists();
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert!(r1.is_ok());
+        assert_eq!(r2.unwrap().len(), 1);
+        assert_eq!(installed, vec![true, true, true]);
+        assert_eq!(updated, vec![false, true, false]);
+        assert!(!shallow);
+        match r3 {
+            Err(InstallError::Command(ref e)) => {
+                assert_eq!(e.flavor, fs[0].repo);
+                assert_eq!(e.operation, Operation::Hook);
+                assert_eq!(e.argv, vec!["sh", "-c", "echo oops >&2; exit 3"]);
+                assert_eq!(e.status.code(), Some(3));
+                assert_eq!(e.stderr, "oops");
+            }
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<(String, Event)>>);
+
+    impl Observer for Recorder {
+        fn notify(&self, name: &str, event: &Event) {
+            if let Event::Progress(..) = *event {
+                return;
+            }
+            self.0.lock().unwrap().push((name.to_owned(), event.clone()));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let dir = testing::temp_dir("rust-vim-flavor-observer-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let first = testing::git(&repo, &["rev-parse", "HEAD"]);
+        let url = repo.to_str().unwrap();
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let fs = [Flavor::new(url)];
+        let recorder = Arc::new(Recorder::default());
+        let opts = Options {
+            hooks: Hooks {
+                post_install: Some("echo out; echo err >&2".to_owned()),
+                post_update: None,
+            },
+            observer: Some(recorder.clone()),
+            ..Options::default()
+        };
+
+        install(&fs, &root, &opts).unwrap();
+        let installed = recorder.0.lock().unwrap().drain(..).map(|(_, e)| e).collect::<Vec<_>>();
+        let second = testing::commit(&repo, "plugin/a.vim", "Second commit");
+        update(&fs, &root, &opts).unwrap();
+        update(&fs, &root, &opts).unwrap();
+        install(&[Flavor::new(dir.join("missing").to_str().unwrap())], &root, &opts).ok();
+        let events = recorder.0.lock().unwrap().clone();
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert_eq!(installed.len(), 5);
+        assert_eq!(installed[..2].to_vec(), vec![Event::Started, Event::Cloning(url.to_owned())]);
+        assert_eq!(installed[4], Event::Installed(first.clone()));
+        let mut hook = installed[2..4].to_vec();
+        hook.sort_by_key(|e| format!("{:?}", e));
+        assert_eq!(hook, vec![
+            Event::HookOutput("err".to_owned()),
+            Event::HookOutput("out".to_owned()),
+        ]);
+        let updates: Vec<Event> = events
+            .iter()
+            .filter(|&&(ref n, _)| n == url)
+            .map(|&(_, ref e)| e.clone())
+            .collect();
+        assert_eq!(updates, vec![
+            Event::Started,
+            Event::Fetching(url.to_owned()),
+            Event::Updated(first, second.clone()),
+            Event::Started,
+            Event::Fetching(url.to_owned()),
+            Event::UpToDate(second),
+        ]);
+        match events.last() {
+            Some(&(_, Event::Failed(ref msg))) => assert!(msg.contains("clone failed")),
+            e => panic!("unexpected event: {:?}", e),
+        }
     }
 
     #[test]
@@ -185,35 +1377,394 @@ mod tests {
         assert!(is_invalid(','));
     }
 
+    #[test]
+    fn test_get_root() {
+        let home = env::home_dir().unwrap();
+        let vim = get_root(Target::Vim, Layout::Pack).unwrap();
+        assert_eq!(vim.path(), home.join(".vim/pack/rflavors").as_path());
+        assert_eq!(vim.layout(), Layout::Pack);
+
+        let nvim = get_root(Target::Nvim, Layout::Pack).unwrap();
+        assert!(nvim.path().ends_with("nvim/site/pack/rflavors"));
+        let nvim = get_root(Target::Nvim, Layout::Flat).unwrap();
+        assert!(nvim.path().ends_with("nvim/rflavors"));
+    }
+
+    #[test]
+    fn test_root_dir() {
+        let mut f = Flavor::new("kana/vim-vspec");
+
+        let root = Root::new("/root", Layout::Flat);
+        assert_eq!(root.dir(&f), Path::new("/root/kana_vim-vspec"));
+
+        let root = Root::new("/root", Layout::Pack);
+        assert_eq!(root.dir(&f), Path::new("/root/start/kana_vim-vspec"));
+
+        f.opt = true;
+        assert_eq!(root.dir(&f), Path::new("/root/opt/kana_vim-vspec"));
+    }
+
+    #[test]
+    fn test_orphans() {
+        let mut dir = env::temp_dir();
+        dir.push("rust-vim-flavor-orphans-test");
+        for d in &["start/a", "start/b", "opt/c", "start/.hidden"] {
+            fs::create_dir_all(dir.join(d)).unwrap();
+        }
+        let root = Root::new(dir.clone(), Layout::Pack);
+        let mut c = Flavor::new("c");
+        c.opt = true;
+
+        let r = orphans(&[Flavor::new("a"), c], &root);
+        let flat = orphans(&[], &Root::new(dir.clone(), Layout::Flat));
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert_eq!(r.unwrap(), vec![dir.join("start").join("b")]);
+        assert_eq!(flat.unwrap(), vec![dir.join("opt"), dir.join("start")]);
+    }
+
+    #[test]
+    fn test_find_flavor_file() {
+        let dir = testing::temp_dir("rust-vim-flavor-find-test");
+        let sub = dir.join("a").join("b");
+        fs::create_dir_all(&sub).unwrap();
+        fs::File::create(dir.join("VimFlavor")).unwrap();
+        fs::create_dir(dir.join("a").join("VimFlavor")).unwrap();
+
+        let found = find_flavor_file(&sub, "VimFlavor");
+        let missing = find_flavor_file(&sub, "NoSuchFlavor");
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert_eq!(found, Some(dir.join("VimFlavor")));
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn test_install_atomic() {
+        let dir = testing::temp_dir("rust-vim-flavor-atomic-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let root = Root::new(dir.join("root"), Layout::Pack);
+        let flavors = [
+            Flavor::new(dir.join("missing").to_str().unwrap()),
+            Flavor::new(repo.to_str().unwrap()),
+        ];
+        let leftover = temp_dir(&root.dir(&flavors[1])).join("plugin");
+        fs::create_dir_all(&leftover).unwrap();
+
+        let r1 = install(&flavors[..1], &root, &Options::default());
+        let r2 = install(&flavors[1..], &root, &Options::default());
+        let entries: Vec<PathBuf> = fs::read_dir(root.path().join("start"))
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        match r1 {
+            Err(InstallError::Command(ref e)) => {
+                assert_eq!(e.flavor, flavors[0].repo);
+                assert_eq!(e.operation, Operation::Clone);
+                assert_eq!(e.argv[..2], ["git", "clone"]);
+                assert!(e.stderr.contains("does not exist"), "{}", e.stderr);
+            }
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(r2.is_ok());
+        assert_eq!(entries, vec![root.dir(&flavors[1])]);
+    }
+
+    #[test]
+    fn test_install_helptags() {
+        let dir = testing::temp_dir("rust-vim-flavor-install-helptags-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        testing::commit(&repo, "doc/a.txt", "*a.txt*");
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let flavors = [
+            Flavor::new(dir.join("missing").to_str().unwrap()),
+            Flavor::new(repo.to_str().unwrap()),
+        ];
+
+        let r = install(&flavors, &root, &Options::default());
+        let tags = root.dir(&flavors[1]).join("doc").join("tags").exists();
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert!(r.is_err());
+        // The tags of the plugin which was installed are generated despite the failure.
+        assert!(tags);
+    }
+
+    #[test]
+    fn test_install_cache() {
+        let dir = testing::temp_dir("rust-vim-flavor-install-cache-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let flavors = [Flavor::new(repo.to_str().unwrap())];
+        let opts = Options {
+            cache: Some(dir.join("cache")),
+            ..Options::default()
+        };
+        let roots = [
+            Root::new(dir.join("root1"), Layout::Flat),
+            Root::new(dir.join("root2"), Layout::Flat),
+        ];
+
+        install(&flavors, &roots[0], &opts).unwrap();
+        let second = testing::commit(&repo, "plugin/a.vim", "Second commit");
+        install(&flavors, &roots[1], &opts).unwrap();
+        let heads: Vec<String> = roots
+            .iter()
+            .map(|r| testing::git(&r.dir(&flavors[0]), &["rev-parse", "HEAD"]))
+            .collect();
+        let r = update(&flavors, &roots[0], &opts);
+        let head = testing::git(&roots[0].dir(&flavors[0]), &["rev-parse", "HEAD"]);
+        let origin = testing::git(&roots[1].dir(&flavors[0]), &["remote", "get-url", "origin"]);
+        let entries = fs::read_dir(dir.join("cache"))
+            .unwrap()
+            .filter(|e| !e.as_ref().unwrap().file_name().to_string_lossy().starts_with('.'))
+            .count();
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert_ne!(heads[0], second);
+        assert_eq!(heads[1], second);
+        assert_eq!(r.unwrap().len(), 1);
+        assert_eq!(head, second);
+        assert_eq!(origin, repo.to_str().unwrap());
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn test_install_offline() {
+        let dir = testing::temp_dir("rust-vim-flavor-offline-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let first = testing::git(&repo, &["rev-parse", "HEAD"]);
+        testing::commit(&repo, "plugin/a.vim", "Second commit");
+        let url = repo.to_str().unwrap();
+        let online = Options {
+            cache: Some(dir.join("cache")),
+            ..Options::default()
+        };
+        install(&[Flavor::new(url)], &Root::new(dir.join("root1"), Layout::Flat), &online).unwrap();
+        let gone = dir.join("gone");
+        fs::rename(&repo, &gone).unwrap();
+
+        let offline = Options {
+            offline: true,
+            ..online
+        };
+        let root = Root::new(dir.join("root2"), Layout::Flat);
+        let locked = Flavor {
+            commit: Some(first.clone()),
+            ..Flavor::new(url)
+        };
+        let r1 = install(&[locked], &root, &offline);
+        let head = testing::git(&root.dir(&Flavor::new(url)), &["rev-parse", "HEAD"]);
+
+        let flavors = [
+            Flavor {
+                commit: Some("0123456789012345678901234567890123456789".to_owned()),
+                ..Flavor::new(url)
+            },
+            Flavor {
+                branch: "dev".to_owned(),
+                ..Flavor::new(url)
+            },
+            Flavor::new("https://example.com/no/cache"),
+        ];
+        let r2 = install(&flavors, &Root::new(dir.join("root3"), Layout::Flat), &offline);
+        let exists = dir.join("root3").exists();
+        let r3 = update(&flavors[1..], &root, &offline);
+        let snapshots = root.path().join(".snapshots").exists();
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert!(r1.is_ok());
+        assert_eq!(head, first);
+        match r2 {
+            Err(InstallError::Missing(m)) => {
+                assert_eq!(m.len(), 3);
+                assert!(m[0].contains("commit 0123456789012345678901234567890123456789"));
+                assert!(m[1].contains("dev is not in"));
+                assert!(m[2].contains("https://example.com/no/cache is not in the cache"));
+            }
+            r => panic!("want Missing, but got {:?}", r),
+        }
+        assert!(exists);
+        assert!(!dir.join("root3").join("no_cache").exists());
+        match r3 {
+            Err(InstallError::Missing(m)) => {
+                assert_eq!(m.len(), 1);
+                assert!(m[0].contains("dev is not in"));
+            }
+            r => panic!("want Missing, but got {:?}", r),
+        }
+        assert!(!snapshots);
+    }
+
     #[test]
     fn test_install() {
         let mut dir = env::temp_dir();
         dir.push("rust-vim-flavor-install-test");
+        let root = Root::new(dir.clone(), Layout::Flat);
 
-        let r = install(&[Flavor::new("vspec")], &dir);
+        let r = install(&[Flavor::new("vspec")], &root, &Options::default());
         assert!(dir.join("vspec").join(".git").exists());
         assert!(r.is_ok());
 
-        let r = install(&[Flavor::new("no/such/vim/plugin")], &dir);
+        let r = install(&[Flavor::new("no/such/vim/plugin")], &root, &Options::default());
         if let Some(e) = remove_dir_all(dir).err() {
             eprintln!("cannot remove a temporary directory: {}", e);
         }
         assert!(r.is_err());
     }
 
+    #[test]
+    fn test_update_changes() {
+        let dir = testing::temp_dir("rust-vim-flavor-update-changes-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let fs = [Flavor::new(repo.to_str().unwrap())];
+
+        install(&fs, &root, &Options::default()).unwrap();
+        let before = testing::git(&repo, &["rev-parse", "HEAD"]);
+        assert_eq!(update(&fs, &root, &Options::default()).unwrap(), vec![]);
+
+        testing::commit(&repo, "doc/a.txt", "Add a document");
+        let after = testing::commit(&repo, "plugin/a.vim", "Fix a bug");
+        let r = update(&fs, &root, &Options::default());
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        let changes = r.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].before, before);
+        assert_eq!(changes[0].after, after);
+        let commits: Vec<(&str, bool)> = changes[0]
+            .commits
+            .iter()
+            .map(|c| (c.subject.as_str(), c.breaking))
+            .collect();
+        assert_eq!(commits, vec![("Fix a bug", false), ("Add a document", true)]);
+    }
+
+    #[test]
+    fn test_update_branch() {
+        let dir = testing::temp_dir("rust-vim-flavor-update-branch-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        testing::git(&repo, &["checkout", "-q", "-b", "dev"]);
+        let dev = testing::commit(&repo, "plugin/a.vim", "Develop");
+        testing::git(&repo, &["checkout", "-q", "master"]);
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let f = Flavor {
+            branch: "dev".to_owned(),
+            ..Flavor::new(repo.to_str().unwrap())
+        };
+        let d = root.dir(&f);
+        install(&[f], &root, &Options::default()).unwrap();
+        assert_eq!(testing::git(&d, &["rev-parse", "HEAD"]), dev);
+
+        let f = Flavor::new(repo.to_str().unwrap());
+        let master = testing::commit(&repo, "plugin/b.vim", "Master");
+        fs::write(d.join("plugin/a.vim"), "modified").unwrap();
+        let r1 = update(&[f], &root, &Options::default()).map(|_| {
+            testing::git(&d, &["rev-parse", "HEAD"])
+        });
+        let b1 = testing::git(&d, &["rev-parse", "--abbrev-ref", "HEAD"]);
+
+        testing::git(&repo, &["commit", "-q", "--amend", "-m", "Force-pushed"]);
+        let forced = testing::git(&repo, &["rev-parse", "HEAD"]);
+        let f = Flavor::new(repo.to_str().unwrap());
+        let r2 = update(&[f], &root, &Options::default()).map(|_| {
+            testing::git(&d, &["rev-parse", "HEAD"])
+        });
+
+        testing::git(&repo, &["tag", "v1.0"]);
+        testing::commit(&repo, "plugin/c.vim", "After v1.0");
+        let f = Flavor {
+            branch: "v1.0".to_owned(),
+            ..Flavor::new(repo.to_str().unwrap())
+        };
+        let r3 = update(&[f], &root, &Options::default()).map(|_| {
+            testing::git(&d, &["rev-parse", "HEAD"])
+        });
+        let b3 = testing::git(&d, &["rev-parse", "--abbrev-ref", "HEAD"]);
+
+        if let Some(e) = remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert_eq!(r1.unwrap(), master);
+        assert_eq!(b1, "master");
+        assert_eq!(r2.unwrap(), forced);
+        assert_eq!(r3.unwrap(), forced);
+        assert_eq!(b3, "HEAD");
+    }
+
+    #[test]
+    fn test_repoint() {
+        let dir = testing::temp_dir("rust-vim-flavor-repoint-test");
+        let fork = PathBuf::from(format!("{}_plugin", dir.display()));
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        testing::repo(&fork);
+        let forked = testing::commit(&fork, "plugin/a.vim", "Forked");
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let f = Flavor::new(repo.to_str().unwrap());
+        let d = root.dir(&f);
+        install(&[f], &root, &Options::default()).unwrap();
+
+        let fs = [Flavor::new(fork.to_str().unwrap())];
+        assert_eq!(root.dir(&fs[0]), d);
+        let r1 = install(&fs, &root, &Options::default()).map(|_| {
+            testing::git(&d, &["rev-parse", "HEAD"])
+        });
+        let url1 = testing::git(&d, &["config", "remote.origin.url"]);
+
+        let f = Flavor::new(repo.to_str().unwrap());
+        let opts = Options {
+            force: true,
+            ..Options::default()
+        };
+        let r2 = update(&[f], &root, &opts);
+        let head2 = testing::git(&d, &["rev-parse", "HEAD"]);
+        let url2 = testing::git(&d, &["config", "remote.origin.url"]);
+        let head = testing::git(&repo, &["rev-parse", "HEAD"]);
+
+        for d in &[&dir, &fork] {
+            if let Some(e) = remove_dir_all(d).err() {
+                eprintln!("cannot remove a temporary directory: {}", e);
+            }
+        }
+        assert_eq!(r1.unwrap(), forked);
+        assert_eq!(url1, fork.to_str().unwrap());
+        let changes = r2.unwrap();
+        assert_eq!(head2, head);
+        assert!(changes[0].recloned);
+        assert!(changes[0].commits.is_empty());
+        assert_eq!(url2, repo.to_str().unwrap());
+    }
+
     #[test]
     fn test_update() {
         let mut dir = env::temp_dir();
         dir.push("rust-vim-flavor-update-test");
+        let root = Root::new(dir.clone(), Layout::Flat);
 
-        let r = install(&[Flavor::new("vspec")], &dir);
+        let r = install(&[Flavor::new("vspec")], &root, &Options::default());
         assert!(r.is_ok());
 
-        let r = update(&[Flavor::new("vspec")], &dir);
+        let r = update(&[Flavor::new("vspec")], &root, &Options::default());
         assert!(dir.join("vspec").join(".git").exists());
         assert!(r.is_ok());
 
-        let r = update(&[Flavor::new("plugin/is/not/installed/yet")], &dir);
+        let r = update(&[Flavor::new("plugin/is/not/installed/yet")], &root, &Options::default());
         if let Some(e) = remove_dir_all(dir).err() {
             eprintln!("cannot remove a temporary directory: {}", e);
         }
@@ -0,0 +1,219 @@
+use std::fs::{self, File};
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process;
+
+use super::{Event, Options, Root};
+
+#[derive(Debug)]
+/// An advisory lock on a root directory, which is released when dropped.
+///
+/// The lock is on a file named `.lock` under the root: `flock(2)` on Unix, and opening it
+/// without sharing on Windows, so that the system releases it even if its holder dies.
+/// The file contains the process ID of its holder, if any.
+pub struct Lock {
+    file: File,
+}
+
+impl Lock {
+    /// Locks `root`. If another process holds the lock, waits for it to be released when
+    /// `opts.wait` is true, and fails with `io::ErrorKind::WouldBlock` otherwise.
+    /// A notice about waiting is sent to `opts.observer`.
+    pub fn acquire(root: &Root, opts: &Options) -> io::Result<Lock> {
+        fs::create_dir_all(root.path())?;
+        let name = root.path().to_string_lossy();
+        Lock::file(&root.path().join(".lock"), &name, opts)
+    }
+
+    /// Locks the file at `path`, which is created if missing, as `acquire` does.
+    /// `name` is what the file protects, which is used in messages.
+    pub fn file(path: &Path, name: &str, opts: &Options) -> io::Result<Lock> {
+        let mut file = match sys::try_lock(path)? {
+            Some(file) => file,
+            None => {
+                // The holder may keep the file from being read.
+                let pid = fs::read_to_string(path).unwrap_or_default();
+                let holder = match pid.trim() {
+                    "" => "another vim-flavor".to_owned(),
+                    pid => format!("another vim-flavor (pid {})", pid),
+                };
+                if !opts.wait {
+                    let msg = format!("{} is locked by {}", name, holder);
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, msg));
+                }
+                let msg = format!("Waiting for {} to finish with {}...", holder, name);
+                opts.notify(name, &Event::Notice(msg));
+                sys::lock(path)?
+            }
+        };
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(process::id().to_string().as_bytes())?;
+        Ok(Lock { file })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock; the file is left for the next holder.
+        self.file.set_len(0).ok();
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::raw::c_int;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+
+    const LOCK_EX: c_int = 2;
+    const LOCK_NB: c_int = 4;
+
+    extern "C" {
+        fn flock(fd: c_int, operation: c_int) -> c_int;
+    }
+
+    /// Opens `path` and locks it, or returns None if another process holds the lock.
+    pub fn try_lock(path: &Path) -> io::Result<Option<File>> {
+        let file = open(path)?;
+        match lock_file(&file, LOCK_EX | LOCK_NB) {
+            Ok(()) => Ok(Some(file)),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Opens `path` and locks it, blocking until another process releases it.
+    pub fn lock(path: &Path) -> io::Result<File> {
+        let file = open(path)?;
+        lock_file(&file, LOCK_EX)?;
+        Ok(file)
+    }
+
+    fn open(path: &Path) -> io::Result<File> {
+        OpenOptions::new().read(true).write(true).create(true).open(path)
+    }
+
+    fn lock_file(file: &File, operation: c_int) -> io::Result<()> {
+        loop {
+            if unsafe { flock(file.as_raw_fd(), operation) } == 0 {
+                return Ok(());
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    /// Opens `path` without sharing, which locks it until it is closed, or returns None if
+    /// another process has it open.
+    pub fn try_lock(path: &Path) -> io::Result<Option<File>> {
+        let r = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .share_mode(0)
+            .open(path);
+        match r {
+            Ok(file) => Ok(Some(file)),
+            Err(ref e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Opens `path` without sharing, polling until another process closes it.
+    pub fn lock(path: &Path) -> io::Result<File> {
+        loop {
+            if let Some(file) = try_lock(path)? {
+                return Ok(file);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::path::Path;
+
+    /// Opens `path`. No lock is available on this platform, so it always succeeds.
+    pub fn try_lock(path: &Path) -> io::Result<Option<File>> {
+        lock(path).map(Some)
+    }
+
+    /// Opens `path`.
+    pub fn lock(path: &Path) -> io::Result<File> {
+        OpenOptions::new().read(true).write(true).create(true).open(path)
+    }
+}
+
+// Other platforms have no lock to test.
+#[cfg(all(test, any(unix, windows)))]
+mod tests {
+    use super::*;
+    use super::super::Layout;
+    use std::thread;
+    use std::time::Duration;
+    use testing;
+
+    #[test]
+    fn test_lock() {
+        let dir = testing::temp_dir("rust-vim-flavor-lock-test");
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let path = root.path().join(".lock");
+
+        let opts = Options {
+            wait: false,
+            ..Options::default()
+        };
+        let lock = Lock::acquire(&root, &opts).unwrap();
+        // Windows does not let others read the file while it is locked.
+        let pid = fs::read_to_string(&path).ok();
+        let locked = Lock::acquire(&root, &opts).map_err(|e| e.kind());
+        drop(lock);
+        let released = fs::read_to_string(&path).unwrap();
+
+        // A lock file left by a process which died holding it does not block.
+        fs::write(&path, "4000000000").unwrap();
+        let stale = Lock::acquire(&root, &opts).map(|_| ());
+
+        let lock = Lock::acquire(&root, &opts).unwrap();
+        let waiter = {
+            let root = root.clone();
+            thread::spawn(move || Lock::acquire(&root, &Options::default()).map(|_| ()))
+        };
+        thread::sleep(Duration::from_millis(100));
+        drop(lock);
+        let waited = waiter.join().unwrap();
+        let entries = fs::read_dir(root.path()).unwrap().count();
+        if let Some(e) = fs::remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        if cfg!(unix) {
+            assert_eq!(pid, Some(process::id().to_string()));
+        }
+        assert_eq!(locked.err(), Some(io::ErrorKind::WouldBlock));
+        assert_eq!(released, "");
+        assert!(stale.is_ok());
+        assert!(waited.is_ok());
+        assert_eq!(entries, 1);
+    }
+}
@@ -0,0 +1,57 @@
+use std::io;
+
+use super::{Flavor, Root};
+use git;
+
+/// Locks each flavor in `fs` to the commit recorded in `content` of a lockfile.
+/// A lockfile consists of lines of a commit, a repository and a branch, as written by
+/// `lockfile`. Flavors whose repository or branch is not recorded are left unlocked.
+pub fn apply_lockfile(fs: &mut [Flavor], content: &str) {
+    for l in content.lines() {
+        let mut l = l.splitn(3, ' ');
+        let (commit, repo, branch) = match (l.next(), l.next(), l.next()) {
+            (Some(c), Some(r), Some(b)) => (c, r, b),
+            _ => continue,
+        };
+        for f in fs.iter_mut().filter(|f| f.repo == repo && f.branch == branch) {
+            f.commit = Some(commit.to_owned());
+        }
+    }
+}
+
+/// Returns the content of a lockfile which locks each flavor in `fs` installed under `root`
+/// to its checked-out commit. Flavors which are not installed keep their `commit`, if any.
+pub fn lockfile(fs: &[Flavor], root: &Root) -> io::Result<String> {
+    let mut content = String::new();
+    for f in fs {
+        let d = root.dir(f);
+        let commit = if d.join(".git").exists() {
+            git::read(&d, &["rev-parse", "HEAD"])?
+        } else {
+            f.commit.clone()
+        };
+        if let Some(commit) = commit {
+            content.push_str(&format!("{} {} {}\n", commit, f.repo, f.branch));
+        }
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Layout;
+
+    #[test]
+    fn test_apply_lockfile() {
+        let mut fs = [Flavor::new("a"), Flavor::new("b"), Flavor::new("c")];
+        fs[2].branch = "dev".to_owned();
+        apply_lockfile(&mut fs, "abc a master\ndef c master\nbroken\n");
+        assert_eq!(fs[0].commit, Some("abc".to_owned()));
+        assert_eq!(fs[1].commit, None);
+        assert_eq!(fs[2].commit, None);
+
+        let root = Root::new("/nonexistent", Layout::Flat);
+        assert_eq!(lockfile(&fs, &root).unwrap(), "abc a master\n");
+    }
+}
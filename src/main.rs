@@ -1,14 +1,20 @@
 extern crate vim_flavor;
 
-use vim_flavor::{Flavor, InstallError, Parser, ParseError, get_root};
+use vim_flavor::{Config, ConfigError, Event, Flavor, InstallError, Json, Layout, Observer,
+                 Options, Outcome, Parser, ParseError, Protocol, Root, Script, Spec, Target,
+                 change_json, error_json, event_json, find_flavor_file, get_cache_path,
+                 get_config_path, get_root, matches, status_json};
 
 use std::env;
 use std::error::Error;
 use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::Read;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
 
 fn main() {
     std::process::exit(run().unwrap_or_else(|e| {
@@ -26,10 +32,7 @@ fn run() -> Result<i32> {
             eprintln!("{}", HELP_MESSAGE);
             2
         }
-        Some(cmd) => {
-            with_cmd(&cmd, args)?;
-            0
-        }
+        Some(cmd) => with_cmd(&cmd, args)?,
     })
 }
 
@@ -45,19 +48,133 @@ Commands:
         help    show this help
         install install Vim plugins according to VimFlavor file
         update  update plugins according to VimFlavor file
+        clean   remove plugins which are not in VimFlavor file
+        list    show the state of each plugin (alias: status)
+        outdated
+                show plugins whose remotes have changed
+        snapshots
+                list snapshots taken before each update
+        rollback
+                restore plugins to a snapshot
+        config  show the effective configuration
+        glue    print a script which makes Vim or Neovim load plugins
+        cache gc
+                remove repositories which have not been used for a while from the cache
+        bundle export|import
+                write plugins into an archive, or install plugins from one without the network
+        vendor  copy plugins into a project directory without their repositories
+        test    run the tests of the plugin in the current directory with vim-vspec
 
 Flags:
 
         -h      same as 'help' command
+
+Flags for commands except help:
+
+        --pack          deploy plugins as a native package under ~/.vim/pack/rflavors
+        --target T      deploy plugins for T: vim (default) or nvim; for nvim, plugins are
+                        deployed as a native package under $XDG_DATA_HOME/nvim/site/pack/rflavors
+        --file PATH     read PATH instead of VimFlavor file; without this flag,
+                        VimFlavor is searched for in the current directory and its parents
+        --root DIR      install plugins under DIR instead of ~/.vim/rflavors
+
+Flags for install, update and config:
+
+        --jobs N        install or update N plugins at a time (default: 1)
+        --depth N       clone with N commits of history; 0 means full history (default: 1);
+                        ignored with the cache, from which plugins are cloned locally
+        --protocol P    same as VIM_FLAVOR_PROTOCOL
+        --host H        same as VIM_FLAVOR_HOST
+
+Flags for install and update:
+
+        --cache         fetch plugins through the cache even if neither VIM_FLAVOR_CACHE nor
+                        the configuration file sets it up, using the default directory
+        --no-cache      clone and fetch plugins without the cache
+        --no-wait       fail instead of waiting when another vim-flavor is using the root
+        --lock          record the checked-out commits in VimFlavor.lock even if it does
+                        not exist yet; an existing one is always kept up to date
+        --offline       use only the cache and repositories on the file system; install fails
+                        with the list of missing repositories and commits if any
+
+Flags for install, update, list and outdated:
+
+        --format F      print text (default) or json, a JSON object per line;
+                        run 'vim-flavor help format' for the objects
+
+Environment:
+
+        VIM_FLAVOR_TARGET       same as --target
+        VIM_FLAVOR_FILE         same as --file
+        VIM_FLAVOR_ROOT         same as --root
+        VIM_FLAVOR_JOBS         same as --jobs
+        VIM_FLAVOR_DEPTH        same as --depth
+        VIM_FLAVOR_PROTOCOL     protocol for shorthand repositories: https (default) or ssh
+        VIM_FLAVOR_HOST         host for shorthand repositories (default: github.com)
+        VIM_FLAVOR_VIM_SCRIPTS  user for single-name repositories (default: vim-scripts);
+                                if empty, single names are used as is
+        VIM_FLAVOR_CACHE        cache of bare repositories shared among roots; if unset or
+                                empty, no cache unless --cache is given, in which case
+                                $XDG_CACHE_HOME/vim-flavor/git is used
+
+Configuration file:
+
+        $XDG_CONFIG_HOME/vim-flavor/config.toml (default: ~/.config/vim-flavor/config.toml)
+
+        target = \"vim\"
+        root = \"~/.vim/rflavors\"
+        file = \"VimFlavor\"
+        jobs = 4
+        depth = 1
+        protocol = \"https\"
+        host = \"github.com\"
+        vim_scripts = \"vim-scripts\"
+        cache = \"~/.cache/vim-flavor/git\"
+
+        [rewrite]
+        # Clone and fetch repositories on GitHub from a local mirror.
+        \"https://github.com/\" = \"file:///srv/mirrors/github/\"
+
+        [hooks]
+        # Run in the directory of each plugin after it is cloned or updated.
+        post_install = \"make\"
+        post_update = \"make\"
+
+        Flags take precedence over environment variables, which take precedence
+        over the configuration file.
+
+Shorthand repositories:
+
+        name            <host>/<vim-scripts>/name
+        user/repo       <host>/user/repo
+        gh:user/repo    github.com/user/repo
+        gitlab:user/repo
+                        gitlab.com/user/repo
+        bitbucket:user/repo
+                        bitbucket.org/user/repo
+        sr.ht:~user/repo
+                        git.sr.ht/~user/repo
 ";
 
-fn with_cmd(cmd: &str, args: env::Args) -> Result<()> {
+fn with_cmd(cmd: &str, args: env::Args) -> Result<i32> {
     match cmd {
-        "help" | "-h" => help(args),
-        "install" => install(args),
-        "update" => update(args),
-        cmd => no_cmd(cmd),
+        "help" | "-h" => help(args)?,
+        "install" => return install(args),
+        "update" => return update(args),
+        "clean" => clean(args)?,
+        "list" | "status" => return list(args),
+        "outdated" => return outdated(args),
+        "test" => return test(args),
+        "snapshots" => snapshots(args)?,
+        "rollback" => rollback(args)?,
+        "config" => return config(args),
+        "glue" => glue(args)?,
+        "cache" => cache(args)?,
+        "bundle" => bundle(args)?,
+        "vendor" => vendor(args)?,
+        cmd => no_cmd(cmd)?,
     }
+    Ok(0)
 }
 
 fn no_cmd(cmd: &str) -> Result<()> {
@@ -83,35 +200,1222 @@ fn help(mut args: env::Args) -> Result<()> {
 fn with_topic(name: &str) -> Result<()> {
     match name {
         "help" => println!("usage: vim-flavor help [topic]"),
-        "install" => println!("usage: vim-flavor install"),
-        "update" => println!("usage: vim-flavor update"),
+        "install" => {
+            println!(
+                "usage: vim-flavor install [--pack] [--force] [--jobs N] [--depth N]\n       \
+                 [--protocol P] [--host H] [--no-wait] [--offline] [--lock] [--format F]\n\n\
+                 Install clones plugins which are not installed yet, and checks out the commits\n\
+                 recorded in VimFlavor.lock next to VimFlavor file, if any.\n\
+                 If the remote of an installed plugin differs from VimFlavor file,\n\
+                 install re-points the remote and re-fetches it; with --force, it\n\
+                 re-clones the plugin instead. After a plugin is cloned, the post_install\n\
+                 hook in the configuration file is run in its directory.\n\
+                 Then, if VimFlavor.lock exists or --lock is given, the checked-out commits\n\
+                 are recorded in it.\n\
+                 With --offline, plugins are installed from the cache or repositories on\n\
+                 the file system without the network."
+            )
+        }
+        "update" => {
+            println!(
+                "usage: vim-flavor update [--pack] [--force] [--jobs N] [--depth N]\n       \
+                 [--protocol P] [--host H] [--no-wait] [--offline] [--lock] [--format F]\n\n\
+                 Update fetches the branch or tag of each plugin and checks it out,\n\
+                 discarding local modifications, and prints the commits pulled in.\n\
+                 Commits marked with '!' touch doc/ or mention BREAKING in their messages.\n\
+                 Remotes which differ from VimFlavor file are handled as install does;\n\
+                 a plugin re-cloned with --force is marked (re-cloned) without commits.\n\
+                 After a plugin is updated, the post_update hook is run in its directory.\n\
+                 Update ignores VimFlavor.lock, and records the new commits in it if it\n\
+                 exists or --lock is given. With --offline, it fails before changing\n\
+                 anything if the branch or tag of any installed plugin is not available."
+            )
+        }
+        "list" | "status" => {
+            println!(
+                "usage: vim-flavor list [--pack] [--protocol P] [--host H] [--format F]\n\n\
+                 List shows, for each flavor, its checked-out branch or tag, commit and date,\n\
+                 whether its working tree is dirty, and whether its remote differs from\n\
+                 VimFlavor file. Directories which no flavor is installed into are listed too."
+            )
+        }
+        "outdated" => {
+            println!(
+                "usage: vim-flavor outdated [--pack] [--protocol P] [--host H] [--format F]\n\n\
+                 Outdated compares the checked-out commit of each plugin with the remote in\n\
+                 VimFlavor file, as install resolves it, without touching the working trees.\n\
+                 It exits with status 1 if any plugin is outdated."
+            )
+        }
+        "format" => {
+            println!(
+                "With --format json, install, update, list and outdated print a JSON object\n\
+                 per line to stdout instead of text. Each object has \"action\", and\n\
+                 \"plugin\", the repository in VimFlavor file, unless noted. Fields which are\n\
+                 not known are null. Objects may gain fields, but never lose them.\n\n\
+                 Install and update print the events of each plugin as they happen:\n\n\
+                 \x20       started\n\
+                 \x20       cloning         url\n\
+                 \x20       fetching        url\n\
+                 \x20       progress        phase, percent (0 to 100)\n\
+                 \x20       repointed       old_url, new_url\n\
+                 \x20       discarding      (local modifications are discarded)\n\
+                 \x20       installed       old (null), new\n\
+                 \x20       up_to_date      old, new (the same commit)\n\
+                 \x20       updated         old, new\n\
+                 \x20       skipped         (not installed yet)\n\
+                 \x20       hook_output     line\n\
+                 \x20       failed          error\n\
+                 \x20       notice          path (of the root instead of plugin), message\n\n\
+                 After updating, update prints the commits pulled into each plugin:\n\n\
+                 \x20       commits         old, new, recloned, commits (objects of id,\n\
+                 \x20                       subject, breaking; empty if recloned)\n\n\
+                 List and outdated print an object for each plugin:\n\n\
+                 \x20       status          state (installed, not_installed or not_git), branch,\n\
+                 \x20                       commit, date, dirty, url, url_matches\n\
+                 \x20       orphan          path (of a directory no flavor is installed into,\n\
+                 \x20                       instead of plugin)\n\
+                 \x20       outdated        branch, old, new\n\n\
+                 If the command fails, it prints an error, one for each plugin which failed,\n\
+                 and exits with status 1:\n\n\
+                 \x20       error           kind (parse, command, missing, io or usage), error\n\n\
+                 Errors in the arguments are printed as objects of kind usage as well if\n\
+                 --format has a value other than text.\n\n\
+                 For example:\n\n\
+                 \x20       {{\"plugin\":\"a/b\",\"action\":\"updated\",\
+                 \"old\":\"1a2b\",\"new\":\"3c4d\"}}"
+            )
+        }
+        "snapshots" => println!("usage: vim-flavor snapshots [--pack]"),
+        "rollback" => {
+            println!(
+                "usage: vim-flavor rollback [--pack] [snapshot]\n\n\
+                 Rollback restores every checkout to the commit recorded in the snapshot.\n\
+                 Without a snapshot, it uses the latest one, which is taken before the\n\
+                 latest update. Run 'vim-flavor snapshots' to list them."
+            )
+        }
+        "config" => {
+            println!(
+                "usage: vim-flavor config [--pack] [--jobs N] [--depth N] [--protocol P]\n       \
+                 [--host H]\n\n\
+                 Config shows the settings in effect, which are taken from flags,\n\
+                 environment variables, the configuration file and built-in defaults,\n\
+                 in this order of precedence. The output is valid as the configuration file.\n\
+                 If the configuration file is broken, config reports the error, shows the\n\
+                 settings without the file, and exits with status 1."
+            )
+        }
+        "cache" => {
+            println!(
+                "usage: vim-flavor cache gc [--days N] [--dry-run]\n\n\
+                 With the cache set up by VIM_FLAVOR_CACHE, the configuration file or\n\
+                 --cache, install and update fetch each plugin into a bare repository in\n\
+                 the cache, and then clone or fetch it from there, so roots share downloads.\n\
+                 The cache is off by default, since each repository in it has full history.\n\
+                 Cache gc removes repositories which have not been used for N days\n\
+                 (default: 30), except those being fetched. With --dry-run, it only lists\n\
+                 them."
+            )
+        }
+        "bundle" => {
+            println!(
+                "usage: vim-flavor bundle export [--pack] ARCHIVE\n       \
+                 vim-flavor bundle import [--pack] [--force] [--overwrite] [--jobs N]\n       \
+                 \x20                        [--protocol P] [--host H] [--no-wait] ARCHIVE\n\n\
+                 Bundle export writes a tar archive of VimFlavor file, a lockfile of the\n\
+                 installed plugins, and their repositories at the checked-out commits.\n\
+                 Every plugin must be installed.\n\
+                 Bundle import installs the plugins in the archive at the recorded commits\n\
+                 without the network, and writes VimFlavor and VimFlavor.lock into the\n\
+                 current directory, or the file given by --file. It refuses to overwrite a\n\
+                 different VimFlavor file without --overwrite."
+            )
+        }
+        "vendor" => {
+            println!(
+                "usage: vim-flavor vendor [--jobs N] [--depth N] [--protocol P] [--host H]\n       \
+                 [--cache] [--no-cache] [--offline] [dir]\n\n\
+                 Vendor copies the files of each plugin at the commit which install would\n\
+                 check out into dir (default: vendor next to VimFlavor file), without .git.\n\
+                 It also writes vim-flavor.manifest there, which records the commit, URL and\n\
+                 directory of each plugin, and removes plugins vendored before but no longer\n\
+                 in VimFlavor file. The root and VimFlavor.lock are left untouched.\n\
+                 To load vendored plugins, for example in a vspec test:\n\n\
+                 \x20       set runtimepath^=vendor/*"
+            )
+        }
+        "test" => {
+            println!(
+                "usage: vim-flavor test [--jobs N] [--depth N] [--protocol P] [--host H]\n       \
+                 [--cache] [--no-cache] [--offline] [--lock] [--filter PATTERN]\n       \
+                 [--junit PATH] [--prove] [file or dir ...]\n\n\
+                 Test is for authors of Vim plugins. It installs every flavor in VimFlavor\n\
+                 file of the plugin, including those in the development group, into\n\
+                 .vim-flavor/deps next to VimFlavor file. kana/vim-vspec is installed too\n\
+                 unless declared. Then, it runs each spec in the given files or directories\n\
+                 (default: t) with vspec, which uses vim in PATH. The plugin itself and the\n\
+                 flavors are on runtimepath. Without VimFlavor file, the current directory is\n\
+                 tested. For example:\n\n\
+                 \x20       flavor 'kana/vim-textobj-user'\n\
+                 \x20       flavor 'kana/vim-vspec', group: 'development'\n\n\
+                 Unlike the original vim-flavor, test runs vspec directly rather than through\n\
+                 prove, so that it parses the TAP output and summarizes each spec with its\n\
+                 failures. Test exits with status 1 if any test fails, or no spec is found.\n\
+                 With --filter, only specs whose paths without .vim contain PATTERN run,\n\
+                 where '*' matches any string and '?' any character.\n\
+                 With --junit, a JUnit XML report is written to PATH.\n\
+                 With --prove, the specs run through prove as in the original vim-flavor,\n\
+                 which reports the results by itself, so --junit cannot be used with it.\n\
+                 As install does, test records the commits of the flavors in VimFlavor.lock\n\
+                 if it exists or --lock is given."
+            )
+        }
+        "glue" => {
+            println!(
+                "usage: vim-flavor glue [--pack] [--lua]\n\n\
+                 Glue prints a script which makes Vim or Neovim load plugins under the root.\n\
+                 With --lua, the script is written in Lua for init.lua of Neovim.\n\
+                 For example, to deploy plugins for both Vim and Neovim from one VimFlavor:\n\n\
+                 \x20       vim-flavor install --pack\n\
+                 \x20       vim-flavor glue --pack > ~/.vim/plugin/rflavors.vim\n\
+                 \x20       vim-flavor install --target nvim\n\
+                 \x20       vim-flavor glue --target nvim --lua > ~/.config/nvim/plugin/rflavors.lua\n\n\
+                 The packages of both are loaded without glue when the roots are the defaults."
+            )
+        }
+        "clean" => {
+            println!(
+                "usage: vim-flavor clean [--pack] [--yes] [--dry-run] [--no-wait]\n\n\
+                 Clean removes directories under the root which no flavor is installed into.\n\
+                 It refuses to run if any of them is not a git checkout, which means that\n\
+                 the root is not one which vim-flavor installs into.\n\
+                 It asks for confirmation unless --yes is given.\n\
+                 With --dry-run, it only lists them. The root is locked as install does,\n\
+                 and --no-wait fails instead of waiting for another vim-flavor."
+            )
+        }
         _ => Err(CLIError::NoTopic(name.to_owned()))?,
     }
     Ok(())
 }
 
-fn install(args: env::Args) -> Result<()> {
-    with_flavor_file(args, vim_flavor::install)
+fn install(args: env::Args) -> Result<i32> {
+    with_format(args, INSTALL_FLAGS, |flags, _| {
+        with_flavor_file(flags, vim_flavor::install)?;
+        Ok(0)
+    })
+}
+
+fn update(args: env::Args) -> Result<i32> {
+    with_format(args, INSTALL_FLAGS, |flags, format| {
+        for c in with_flavor_file(flags, vim_flavor::update)? {
+            if format == Format::Json {
+                println!("{}", change_json(&c));
+                continue;
+            }
+            if c.recloned {
+                println!("{}: {}..{} (re-cloned)", c.repo, short(&c.before), short(&c.after));
+                continue;
+            }
+            println!("{}: {}..{}", c.repo, short(&c.before), short(&c.after));
+            for commit in c.commits {
+                let mark = if commit.breaking { "!" } else { " " };
+                println!("  {} {} {}", mark, commit.id, commit.subject);
+            }
+        }
+        Ok(0)
+    })
+}
+
+fn clean(args: env::Args) -> Result<()> {
+    let flags = Flags::parse(args, &["--pack", "--yes", "--dry-run", "--no-wait"], 0)?;
+    let root = flags.root()?;
+    let (fs, _) = runtime(read_flavor_file(&flags.file()?)?);
+    if !root.path().is_dir() {
+        return Ok(());
+    }
+    // Keeps install and update from adding plugins while the orphans are being removed.
+    let _lock = vim_flavor::Lock::acquire(&root, &flags.options()?)?;
+    let dirs = vim_flavor::orphans(&fs, &root)?;
+    if dirs.is_empty() {
+        return Ok(());
+    }
+    // Every plugin is a clone, so other directories mean that the root is not ours.
+    if let Some(d) = dirs.iter().find(|d| !d.join(".git").exists()) {
+        return Err(CLIError::NotRoot(d.display().to_string()));
+    }
+    for d in &dirs {
+        println!("{}", d.display());
+    }
+    if flags.dry_run || !flags.yes && !confirm("Remove these directories?")? {
+        return Ok(());
+    }
+    for d in dirs {
+        fs::remove_dir_all(d)?;
+    }
+    Ok(())
+}
+
+fn list(args: env::Args) -> Result<i32> {
+    with_format(args, &["--pack", "--protocol", "--host", "--format"], |flags, format| {
+        if format == Format::Json {
+            list_json(flags)
+        } else {
+            list_text(flags)
+        }?;
+        Ok(0)
+    })
+}
+
+fn list_text(flags: &Flags) -> Result<()> {
+    let root = flags.root()?;
+    let opts = flags.options()?;
+    let (fs, _) = runtime(read_flavor_file(&flags.file()?)?);
+    for f in &fs {
+        let s = match vim_flavor::status(f, &root, &opts)? {
+            Some(s) => s,
+            None => {
+                println!("{}: not installed", f.repo);
+                continue;
+            }
+        };
+        if s.commit.is_empty() {
+            println!("{}: not a git repository", f.repo);
+            continue;
+        }
+        print!(
+            "{}: {} {} ({})",
+            f.repo,
+            s.branch.as_ref().map_or("(detached)", |b| b),
+            s.commit,
+            s.date
+        );
+        if s.dirty {
+            print!(", dirty");
+        }
+        if !s.url_matches {
+            print!(
+                ", origin is {}",
+                s.url.as_ref().map_or("missing", |u| u)
+            );
+        }
+        println!();
+    }
+    for d in vim_flavor::orphans(&fs, &root)? {
+        println!("{}: not in VimFlavor", d.display());
+    }
+    Ok(())
+}
+
+fn list_json(flags: &Flags) -> Result<()> {
+    let root = flags.root()?;
+    let opts = flags.options()?;
+    let (fs, _) = runtime(read_flavor_file(&flags.file()?)?);
+    for f in &fs {
+        let s = vim_flavor::status(f, &root, &opts)?;
+        println!("{}", status_json(&f.repo, s.as_ref()));
+    }
+    for d in vim_flavor::orphans(&fs, &root)? {
+        println!(
+            "{}",
+            Json::Object(vec![
+                ("action", "orphan".into()),
+                ("path", d.to_string_lossy().into_owned().into()),
+            ])
+        );
+    }
+    Ok(())
+}
+
+fn outdated(args: env::Args) -> Result<i32> {
+    with_format(args, &["--pack", "--protocol", "--host", "--format"], |flags, format| {
+        let root = flags.root()?;
+        let opts = flags.options()?;
+        let mut code = 0;
+        for f in &runtime(read_flavor_file(&flags.file()?)?).0 {
+            if let Some(o) = vim_flavor::outdated(f, &root, &opts)? {
+                if format == Format::Json {
+                    println!(
+                        "{}",
+                        Json::Object(vec![
+                            ("plugin", f.repo.as_str().into()),
+                            ("action", "outdated".into()),
+                            ("branch", f.branch.as_str().into()),
+                            ("old", o.current.into()),
+                            ("new", o.available.into()),
+                        ])
+                    );
+                } else {
+                    println!(
+                        "{}: {} \u{2192} {} ({})",
+                        f.repo,
+                        short(&o.current),
+                        o.available.as_ref().map_or("(none)", |c| short(c)),
+                        f.branch
+                    );
+                }
+                code = 1;
+            }
+        }
+        Ok(code)
+    })
+}
+
+/// The test framework which `test` runs specs with.
+const VSPEC: &'static str = "kana/vim-vspec";
+
+fn test(args: env::Args) -> Result<i32> {
+    let flags = Flags::parse(
+        args,
+        &[
+            "--cache",
+            "--no-cache",
+            "--no-wait",
+            "--offline",
+            "--lock",
+            "--jobs",
+            "--depth",
+            "--protocol",
+            "--host",
+            "--junit",
+            "--filter",
+            "--prove",
+        ],
+        usize::max_value(),
+    )?;
+    if flags.prove && flags.junit.is_some() {
+        let msg = "no report is written with --prove".to_owned();
+        return Err(CLIError::InvalidValue("--junit".to_owned(), msg));
+    }
+    let file = match flags.file() {
+        Ok(file) => file,
+        Err(CLIError::NoFlavorFile(name)) => env::current_dir()?.join(name),
+        Err(e) => return Err(e),
+    };
+    let (mut fs, locked) = if file.exists() {
+        read_locked(&file)?
+    } else {
+        (vec![], String::new())
+    };
+    let plugin = match file.parent() {
+        Some(p) if p != Path::new("") => p.canonicalize()?,
+        _ => env::current_dir()?,
+    };
+    let root = Root::new(plugin.join(".vim-flavor").join("deps"), Layout::Flat);
+    let opts = flags.options()?;
+    let declared = fs.len();
+    if !fs.iter().any(|f| vim_flavor::same_repo(&f.repo, VSPEC, &opts)) {
+        fs.push(Flavor {
+            group: "development".to_owned(),
+            ..Flavor::new(VSPEC)
+        });
+    }
+    vim_flavor::install(&fs, &root, &opts)?;
+    let content = vim_flavor::lockfile(&fs[..declared], &root)?;
+    if file.exists() && flags.writes_lockfile(&file) && !content.is_empty() && content != locked {
+        fs::write(lock_path(&file), content)?;
+    }
+
+    let mut rtp = vec![plugin.clone()];
+    let mut vspec = PathBuf::new();
+    for f in &fs {
+        if vim_flavor::same_repo(&f.repo, VSPEC, &opts) {
+            vspec = root.dir(f).join("bin").join("vspec");
+        } else {
+            rtp.push(root.dir(f));
+        }
+    }
+    let paths = if flags.args.is_empty() {
+        vec!["t".to_owned()]
+    } else {
+        flags.args.clone()
+    };
+    let mut specs = Vec::new();
+    for p in paths {
+        let path = plugin.join(&p);
+        if !path.is_dir() {
+            if path.exists() {
+                specs.push(p);
+            }
+            continue;
+        }
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&path)? {
+            let name = entry?.file_name();
+            if name.to_string_lossy().ends_with(".vim") {
+                names.push(Path::new(&p).join(name).to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        specs.extend(names);
+    }
+    if let Some(ref pattern) = flags.filter {
+        specs.retain(|s| {
+            let name = if s.ends_with(".vim") { &s[..s.len() - 4] } else { s };
+            matches(pattern, name)
+        });
+    }
+    if specs.is_empty() {
+        return Err(CLIError::NoSpecs);
+    }
+    if flags.prove {
+        return prove(&vspec, &rtp, &specs, &plugin);
+    }
+
+    let mut results = Vec::new();
+    for spec in &specs {
+        let output = Command::new(&vspec)
+            .args(&rtp)
+            .arg(spec)
+            .current_dir(&plugin)
+            .output()?;
+        let mut result = vim_flavor::parse_tap(spec, &String::from_utf8_lossy(&output.stdout));
+        if result.error.is_none() && !output.status.success() {
+            result.error = Some(format!("vspec exited with {}", output.status));
+        }
+        if result.error.is_some() {
+            io::stderr().write_all(&output.stderr)?;
+        }
+        report(&result);
+        results.push(result);
+    }
+    let failed = results.iter().filter(|r| r.failed()).count();
+    let (passed, failures, skipped) = results.iter().map(|r| r.counts()).fold(
+        (0, 0, 0),
+        |a, c| (a.0 + c.0, a.1 + c.1, a.2 + c.2),
+    );
+    println!(
+        "{} of {} specs failed; {} passed, {} failed, {} skipped",
+        failed,
+        results.len(),
+        passed,
+        failures,
+        skipped
+    );
+    if let Some(ref path) = flags.junit {
+        fs::write(path, vim_flavor::junit(&results))?;
+    }
+    Ok(if failed > 0 { 1 } else { 0 })
+}
+
+/// Runs `specs` through prove with `vspec`, and returns the exit status of prove.
+fn prove(vspec: &Path, rtp: &[PathBuf], specs: &[String], plugin: &Path) -> Result<i32> {
+    // prove appends each spec to the command, which vspec takes as the last argument.
+    let mut exec = vec![vspec.display().to_string()];
+    exec.extend(rtp.iter().map(|d| d.display().to_string()));
+    let status = Command::new("prove")
+        .args(&["--comments", "--ext", ".vim", "--exec", &exec.join(" ")])
+        .args(specs)
+        .current_dir(plugin)
+        .status()?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Prints the counts of tests in `spec`, and the failures with their diagnostics.
+fn report(spec: &Spec) {
+    let (passed, failed, skipped) = spec.counts();
+    println!(
+        "{}: {} passed, {} failed, {} skipped",
+        spec.name,
+        passed,
+        failed,
+        skipped
+    );
+    for t in spec.tests.iter().filter(|t| t.outcome == Outcome::Failed) {
+        println!("  not ok - {}", t.name);
+        for d in &t.diagnostics {
+            println!("    # {}", d);
+        }
+    }
+    if let Some(ref e) = spec.error {
+        println!("  {}", e);
+    }
+}
+
+fn snapshots(args: env::Args) -> Result<()> {
+    let flags = Flags::parse(args, &["--pack"], 0)?;
+    for name in vim_flavor::snapshots(&flags.root()?)? {
+        println!("{}", name);
+    }
+    Ok(())
 }
 
-fn update(args: env::Args) -> Result<()> {
-    with_flavor_file(args, vim_flavor::update)
+fn rollback(args: env::Args) -> Result<()> {
+    let flags = Flags::parse(args, &["--pack"], 1)?;
+    let name = flags.args.get(0).map(|s| s.as_str());
+    let name = vim_flavor::rollback(&flags.root()?, name, &flags.options()?)?;
+    eprintln!("Rolled back to {}.", name);
+    Ok(())
 }
 
-fn with_flavor_file(
-    mut args: env::Args,
-    fun: fn(&[Flavor], &Path) -> std::result::Result<(), InstallError>,
-) -> Result<()> {
-    if args.next().is_some() {
-        return Err(CLIError::TooManyArguments);
+fn config(args: env::Args) -> Result<i32> {
+    let mut flags = Flags::parse_args(
+        args,
+        &["--pack", "--jobs", "--depth", "--protocol", "--host"],
+        0,
+    )?;
+    // A broken configuration file is reported rather than hiding the other settings.
+    let code = match read_config() {
+        Ok(config) => {
+            flags.config = config;
+            0
+        }
+        Err(e) => {
+            eprintln!("{}\nThe configuration file is ignored.", e);
+            1
+        }
+    };
+    let root = flags.root()?;
+    let opts = flags.options()?;
+    println!("target = {}", quote(flags.target()?.name()));
+    println!("root = {}", quote(&root.path().to_string_lossy()));
+    let file = flags.file().unwrap_or_else(|_| PathBuf::from(flags.file_name()));
+    println!("file = {}", quote(&file.to_string_lossy()));
+    println!("jobs = {}", opts.jobs);
+    println!("depth = {}", opts.depth.unwrap_or(0));
+    println!("protocol = {}", quote(opts.protocol.name()));
+    println!("host = {}", quote(&opts.host));
+    println!(
+        "vim_scripts = {}",
+        quote(opts.vim_scripts.as_ref().map_or("", |u| u))
+    );
+    println!(
+        "cache = {}",
+        quote(&opts.cache.as_ref().map_or("".into(), |c| c.to_string_lossy()))
+    );
+    if !opts.rewrites.is_empty() {
+        println!("\n[rewrite]");
+        for &(ref from, ref to) in &opts.rewrites {
+            println!("{} = {}", quote(from), quote(to));
+        }
     }
-    let name = "VimFlavor";
-    let mut f = File::open(name)?;
+    let hooks = [
+        ("post_install", &opts.hooks.post_install),
+        ("post_update", &opts.hooks.post_update),
+    ];
+    if hooks.iter().any(|&(_, h)| h.is_some()) {
+        println!("\n[hooks]");
+        for &(name, hook) in &hooks {
+            if let Some(ref h) = *hook {
+                println!("{} = {}", name, quote(h));
+            }
+        }
+    }
+    Ok(code)
+}
+
+fn cache(args: env::Args) -> Result<()> {
+    let flags = Flags::parse(args, &["--days", "--dry-run"], 1)?;
+    match flags.args.get(0).map(|s| s.as_str()) {
+        Some("gc") => (),
+        Some(cmd) => return Err(CLIError::NoCommand(format!("cache {}", cmd))),
+        None => return Err(CLIError::NoCommand("cache".to_owned())),
+    }
+    let days = match flags.days {
+        Some(ref d) => d.parse().map_err(|_| {
+            CLIError::InvalidValue("--days".to_owned(), d.clone())
+        })?,
+        None => 30,
+    };
+    let cache = match flags.options()?.cache.or_else(get_cache_path) {
+        Some(cache) => cache,
+        None => return Ok(()),
+    };
+    let dirs = if flags.dry_run {
+        vim_flavor::unused(&cache, days)?
+    } else {
+        vim_flavor::remove_unused(&cache, days)?
+    };
+    for d in dirs {
+        println!("{}", d.display());
+    }
+    Ok(())
+}
+
+fn bundle(args: env::Args) -> Result<()> {
+    let flags = Flags::parse(
+        args,
+        &[
+            "--pack",
+            "--force",
+            "--overwrite",
+            "--no-wait",
+            "--jobs",
+            "--protocol",
+            "--host",
+        ],
+        2,
+    )?;
+    let cmd = match flags.args.get(0).map(|s| s.as_str()) {
+        Some(cmd @ "export") | Some(cmd @ "import") => cmd,
+        Some(cmd) => return Err(CLIError::NoCommand(format!("bundle {}", cmd))),
+        None => return Err(CLIError::NoCommand("bundle".to_owned())),
+    };
+    let archive = match flags.args.get(1) {
+        Some(a) => PathBuf::from(a),
+        None => return Err(CLIError::NoValue(format!("bundle {}", cmd))),
+    };
+    if cmd == "import" {
+        return import_bundle(&flags, &archive);
+    }
+    let file = flags.file()?;
+    let mut content = String::new();
+    File::open(&file)?.read_to_string(&mut content)?;
+    let (fs, _) = runtime(Parser::new(&content).parse()?);
+    vim_flavor::export_bundle(&content, &fs, &flags.root()?, &archive)?;
+    Ok(())
+}
+
+/// Installs the plugins in `archive`, and writes its flavor file and lockfile.
+fn import_bundle(flags: &Flags, archive: &Path) -> Result<()> {
+    let file = flags
+        .file
+        .clone()
+        .or_else(|| env::var("VIM_FLAVOR_FILE").ok())
+        .map_or_else(|| PathBuf::from(flags.file_name()), PathBuf::from);
+    let options = flags.options()?;
+    let dir = create_temp_dir("vim-flavor-bundle")?;
+    let r = vim_flavor::extract_bundle(archive, &dir, &options)
+        .map_err(CLIError::from)
+        .and_then(|b| {
+            match fs::read_to_string(&file) {
+                Ok(ref content) if *content != b.flavor_file && !flags.overwrite => {
+                    return Err(CLIError::FileExists(file.display().to_string()))
+                }
+                Ok(_) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(CLIError::IO(e)),
+            }
+            let opts = Options {
+                cache: Some(b.cache.clone()),
+                offline: true,
+                ..options
+            };
+            vim_flavor::install(&runtime(b.flavors).0, &flags.root()?, &opts)?;
+            fs::write(&file, &b.flavor_file)?;
+            fs::write(lock_path(&file), &b.lockfile)?;
+            Ok(())
+        });
+    let removed = fs::remove_dir_all(&dir);
+    r?;
+    Ok(removed?)
+}
+
+/// Creates a new directory under the temporary directory, whose name starts with `prefix`.
+/// A directory which already exists is never reused, as it may be stale or planted.
+fn create_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    for n in 0.. {
+        let dir = env::temp_dir().join(format!("{}-{}-{}", prefix, std::process::id(), n));
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+fn vendor(args: env::Args) -> Result<()> {
+    let flags = Flags::parse(
+        args,
+        &[
+            "--cache",
+            "--no-cache",
+            "--offline",
+            "--jobs",
+            "--depth",
+            "--protocol",
+            "--host",
+        ],
+        1,
+    )?;
+    let file = flags.file()?;
+    let (fs, _) = read_locked(&file)?;
+    let dir = match flags.args.get(0) {
+        Some(d) => PathBuf::from(d),
+        None => file.with_file_name("vendor"),
+    };
+    vim_flavor::vendor(&fs, &dir, &flags.options()?)?;
+    Ok(())
+}
+
+fn glue(args: env::Args) -> Result<()> {
+    let flags = Flags::parse(args, &["--pack", "--lua"], 0)?;
+    let root = flags.root()?;
+    let script = if flags.lua { Script::Lua } else { Script::Vim };
+    match vim_flavor::glue(&root, script) {
+        Some(s) => print!("{}", s),
+        None => return Err(CLIError::NotPackage(root.path().display().to_string())),
+    }
+    Ok(())
+}
+
+/// Quotes `s` as a basic string of TOML.
+fn quote(s: &str) -> String {
+    let mut q = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => q.push_str("\\\""),
+            '\\' => q.push_str("\\\\"),
+            '\n' => q.push_str("\\n"),
+            '\t' => q.push_str("\\t"),
+            ch => q.push(ch),
+        }
+    }
+    q.push('"');
+    q
+}
+
+fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
+
+fn confirm(prompt: &str) -> io::Result<bool> {
+    eprint!("{} [y/N] ", prompt);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim() == "y" || answer.trim() == "yes")
+}
+
+/// Flags accepted by every command except help.
+const COMMON_FLAGS: &'static [&'static str] = &["--file", "--root", "--target"];
+
+const INSTALL_FLAGS: &'static [&'static str] =
+    &[
+        "--pack",
+        "--lock",
+        "--format",
+        "--force",
+        "--no-wait",
+        "--cache",
+        "--no-cache",
+        "--offline",
+        "--jobs",
+        "--depth",
+        "--protocol",
+        "--host",
+    ];
+
+/// Runs `fun` with the flavors in the runtime group of the flavor file, which are locked to
+/// the commits in its lockfile, and then records the checked-out commits in the lockfile if it
+/// exists or --lock is given. The commits of the other flavors are kept.
+fn with_flavor_file<T>(
+    flags: &Flags,
+    fun: fn(&[Flavor], &Root, &Options) -> std::result::Result<T, InstallError>,
+) -> Result<T> {
+    let file = flags.file()?;
+    let (all, locked) = read_locked(&file)?;
+    let (fs, _) = runtime(all.clone());
+    let root = flags.root()?;
+    let r = fun(&fs, &root, &flags.options()?)?;
+    // The lines are in the order of the declarations, as `test` writes them.
+    let content = vim_flavor::lockfile(&all, &root)?;
+    if flags.writes_lockfile(&file) && !content.is_empty() && content != locked {
+        fs::write(lock_path(&file), content)?;
+    }
+    Ok(r)
+}
+
+/// Reads the flavor file `file` and locks the flavors to the commits in its lockfile.
+/// Returns the flavors and the content of the lockfile, which is empty if missing.
+fn read_locked(file: &Path) -> Result<(Vec<Flavor>, String)> {
+    let mut fs = read_flavor_file(file)?;
+    let locked = match fs::read_to_string(lock_path(file)) {
+        Ok(content) => content,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(CLIError::IO(e)),
+    };
+    vim_flavor::apply_lockfile(&mut fs, &locked);
+    Ok((fs, locked))
+}
+
+/// Returns the path of the lockfile of the flavor file `file`.
+fn lock_path(file: &Path) -> PathBuf {
+    let mut lock = file.as_os_str().to_owned();
+    lock.push(".lock");
+    PathBuf::from(lock)
+}
+
+/// Splits `fs` into the flavors in the runtime group, which are installed into the root,
+/// and the others, which only `test` installs.
+fn runtime(fs: Vec<Flavor>) -> (Vec<Flavor>, Vec<Flavor>) {
+    fs.into_iter().partition(|f| f.group == "runtime")
+}
+
+fn read_config() -> Result<Config> {
+    let path = match get_config_path() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+    let mut buffer = String::new();
+    match File::open(&path) {
+        Ok(mut f) => f.read_to_string(&mut buffer)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(CLIError::IO(e)),
+    };
+    Config::parse(&buffer).map_err(|e| {
+        CLIError::Config(path.display().to_string(), e)
+    })
+}
+
+fn read_flavor_file(path: &Path) -> Result<Vec<Flavor>> {
+    let mut f = File::open(path)?;
     let mut buffer = String::new();
     f.read_to_string(&mut buffer)?;
-    let root = get_root().ok_or(CLIError::GetHome)?;
-    fun(&Parser::new(&buffer).parse()?, &root)?;
-    Ok(())
+    Ok(Parser::new(&buffer).parse()?)
+}
+
+#[derive(Default)]
+struct Flags {
+    pack: bool,
+    force: bool,
+    yes: bool,
+    no_wait: bool,
+    cache: bool,
+    no_cache: bool,
+    lock: bool,
+    overwrite: bool,
+    offline: bool,
+    dry_run: bool,
+    lua: bool,
+    prove: bool,
+    target: Option<String>,
+    jobs: Option<String>,
+    days: Option<String>,
+    depth: Option<String>,
+    protocol: Option<String>,
+    host: Option<String>,
+    junit: Option<String>,
+    filter: Option<String>,
+    format: Option<String>,
+    file: Option<String>,
+    root: Option<String>,
+    args: Vec<String>,
+    config: Config,
+}
+
+impl Flags {
+    /// Parses `args` and reads the configuration file.
+    fn parse<I: Iterator<Item = String>>(args: I, known: &[&str], max: usize) -> Result<Flags> {
+        let mut flags = Flags::parse_args(args, known, max)?;
+        flags.config = read_config()?;
+        Ok(flags)
+    }
+
+    /// Parses `args` without reading the configuration file.
+    fn parse_args<I>(mut args: I, known: &[&str], max: usize) -> Result<Flags>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut flags = Flags::default();
+        while let Some(arg) = args.next() {
+            let (name, value) = match arg.find('=') {
+                Some(i) if arg.starts_with("--") => (&arg[..i], Some(arg[i + 1..].to_owned())),
+                _ => (arg.as_str(), None),
+            };
+            let slot = match name {
+                s if s.starts_with('-') && !known.contains(&s) && !COMMON_FLAGS.contains(&s) => {
+                    return Err(CLIError::NoFlag(arg.clone()))
+                }
+                "--target" => &mut flags.target,
+                "--file" => &mut flags.file,
+                "--root" => &mut flags.root,
+                "--jobs" => &mut flags.jobs,
+                "--days" => &mut flags.days,
+                "--depth" => &mut flags.depth,
+                "--protocol" => &mut flags.protocol,
+                "--host" => &mut flags.host,
+                "--junit" => &mut flags.junit,
+                "--filter" => &mut flags.filter,
+                "--format" => &mut flags.format,
+                _ if value.is_some() => return Err(CLIError::NoFlag(arg.clone())),
+                "--pack" => {
+                    flags.pack = true;
+                    continue;
+                }
+                "--force" => {
+                    flags.force = true;
+                    continue;
+                }
+                "--yes" => {
+                    flags.yes = true;
+                    continue;
+                }
+                "--dry-run" => {
+                    flags.dry_run = true;
+                    continue;
+                }
+                "--no-wait" => {
+                    flags.no_wait = true;
+                    continue;
+                }
+                "--cache" => {
+                    flags.cache = true;
+                    continue;
+                }
+                "--lock" => {
+                    flags.lock = true;
+                    continue;
+                }
+                "--overwrite" => {
+                    flags.overwrite = true;
+                    continue;
+                }
+                "--no-cache" => {
+                    flags.no_cache = true;
+                    continue;
+                }
+                "--offline" => {
+                    flags.offline = true;
+                    continue;
+                }
+                "--lua" => {
+                    flags.lua = true;
+                    continue;
+                }
+                "--prove" => {
+                    flags.prove = true;
+                    continue;
+                }
+                _ if flags.args.len() < max => {
+                    flags.args.push(arg.clone());
+                    continue;
+                }
+                _ => return Err(CLIError::TooManyArguments),
+            };
+            *slot = Some(value.or_else(|| args.next()).ok_or_else(|| {
+                CLIError::NoValue(name.to_owned())
+            })?);
+        }
+        Ok(flags)
+    }
+
+    fn target(&self) -> Result<Target> {
+        let target = setting(&self.target, "--target", "VIM_FLAVOR_TARGET", Target::from_name)?;
+        Ok(target.or(self.config.target).unwrap_or(Target::Vim))
+    }
+
+    /// Returns the root given by --root, `VIM_FLAVOR_ROOT`, the configuration file or
+    /// `get_root`, in this order of precedence. Plugins for Neovim are always deployed
+    /// as a native package.
+    fn root(&self) -> Result<Root> {
+        let target = self.target()?;
+        let layout = if self.pack || target == Target::Nvim {
+            Layout::Pack
+        } else {
+            Layout::Flat
+        };
+        // An empty flag or variable is unset, so that the others are used.
+        let value = self.root.clone().filter(|r| !r.is_empty());
+        let value = value.or_else(|| env::var("VIM_FLAVOR_ROOT").ok());
+        match self.config.resolve_root(value) {
+            Some(path) => Ok(Root::new(path, layout)),
+            None => get_root(target, layout).ok_or(CLIError::GetHome),
+        }
+    }
+
+    /// Returns the flavor file given by --file or `VIM_FLAVOR_FILE`. Otherwise, searches
+    /// the current directory and its parents for the file named in the configuration file,
+    /// or VimFlavor.
+    fn file(&self) -> Result<PathBuf> {
+        if let Some(f) = self.file.clone().or_else(|| env::var("VIM_FLAVOR_FILE").ok()) {
+            return Ok(PathBuf::from(f));
+        }
+        let name = self.file_name();
+        find_flavor_file(&env::current_dir()?, name).ok_or_else(|| {
+            CLIError::NoFlavorFile(name.to_owned())
+        })
+    }
+
+    /// Reports whether the lockfile of the flavor file `file` is to be written, that is,
+    /// it exists or --lock is given.
+    fn writes_lockfile(&self, file: &Path) -> bool {
+        self.lock || lock_path(file).exists()
+    }
+
+    fn format(&self) -> Result<Format> {
+        match self.format.as_ref().map(|s| s.as_str()) {
+            None | Some("text") => Ok(Format::Text),
+            Some("json") => Ok(Format::Json),
+            Some(f) => Err(CLIError::InvalidValue("--format".to_owned(), f.to_owned())),
+        }
+    }
+
+    fn file_name(&self) -> &str {
+        self.config.file.as_ref().map_or("VimFlavor", |f| f)
+    }
+
+    /// Resolves settings from flags, environment variables and the configuration file,
+    /// in this order of precedence.
+    fn options(&self) -> Result<Options> {
+        let config = &self.config;
+        let default = Options::default();
+        let jobs = setting(&self.jobs, "--jobs", "VIM_FLAVOR_JOBS", |s| {
+            s.parse().ok().filter(|&n| n > 0)
+        })?;
+        let depth = setting(&self.depth, "--depth", "VIM_FLAVOR_DEPTH", |s| s.parse().ok())?;
+        let protocol = setting(
+            &self.protocol,
+            "--protocol",
+            "VIM_FLAVOR_PROTOCOL",
+            Protocol::from_name,
+        )?;
+        let host = setting(&self.host, "--host", "VIM_FLAVOR_HOST", |s| Some(s.to_owned()))?;
+        let vim_scripts = env::var("VIM_FLAVOR_VIM_SCRIPTS").ok().or_else(|| {
+            config.vim_scripts.clone()
+        });
+        // The cache is opt-in, because mirrors in it are not shallow.
+        let cache = env::var_os("VIM_FLAVOR_CACHE")
+            .map(PathBuf::from)
+            .or_else(|| config.cache.clone())
+            .filter(|c| !c.as_os_str().is_empty())
+            .or_else(|| if self.cache { get_cache_path() } else { None })
+            .filter(|_| !self.no_cache);
+        let observer: Arc<Observer> = match self.format()? {
+            Format::Text => Arc::new(Printer { force: self.force }),
+            Format::Json => Arc::new(JsonPrinter),
+        };
+        Ok(Options {
+            force: self.force,
+            protocol: protocol.or(config.protocol).unwrap_or(default.protocol),
+            host: host.or_else(|| config.host.clone()).unwrap_or(default.host),
+            vim_scripts: match vim_scripts {
+                Some(u) => if u.is_empty() { None } else { Some(u) },
+                None => default.vim_scripts,
+            },
+            rewrites: config.rewrites.clone(),
+            depth: match depth.or(config.depth) {
+                Some(0) => None,
+                Some(d) => Some(d),
+                None => default.depth,
+            },
+            jobs: jobs.or(config.jobs).unwrap_or(default.jobs),
+            hooks: config.hooks.clone(),
+            wait: !self.no_wait,
+            cache,
+            offline: self.offline,
+            observer: Some(observer),
+        })
+    }
+}
+
+#[derive(Debug)]
+/// Prints warnings and notices of the library to stderr.
+struct Printer {
+    force: bool,
+}
+
+impl Observer for Printer {
+    fn notify(&self, name: &str, event: &Event) {
+        match *event {
+            Event::Repointed(ref from, ref to) => {
+                eprintln!(
+                    "{}: origin is {}; {} {}.",
+                    name,
+                    from.as_ref().map_or("missing", |f| f),
+                    if self.force { "re-cloning from" } else { "switching to" },
+                    to
+                )
+            }
+            Event::Discarding => eprintln!("Warning: {}: discarding local modifications.", name),
+            Event::Skipped => eprintln!("Skipped {}: not installed yet.", name),
+            Event::Notice(ref msg) => eprintln!("{}", msg),
+            _ => (),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Prints every event of the library to stdout as a JSON object, one per line.
+struct JsonPrinter;
+
+impl Observer for JsonPrinter {
+    fn notify(&self, name: &str, event: &Event) {
+        println!("{}", event_json(name, event));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The format of the output of install, update, list and outdated.
+enum Format {
+    Text,
+    Json,
+}
+
+/// Parses `args` as `Flags::parse` does, and runs `fun` with the flags and the format given
+/// by --format. In JSON, an error is printed to stdout as an object, and the exit status is 1.
+/// Errors in the arguments are printed as JSON as well if --format has a value other than
+/// text, since the caller expects something other than text.
+fn with_format<F>(args: env::Args, known: &[&str], fun: F) -> Result<i32>
+where
+    F: FnOnce(&Flags, Format) -> Result<i32>,
+{
+    let args: Vec<String> = args.collect();
+    let mut format = Format::Text;
+    for (i, arg) in args.iter().enumerate() {
+        let value = if arg == "--format" {
+            args.get(i + 1).map(|s| s.as_str())
+        } else if arg.starts_with("--format=") {
+            Some(&arg["--format=".len()..])
+        } else {
+            continue;
+        };
+        format = if value == Some("text") { Format::Text } else { Format::Json };
+    }
+    let r = Flags::parse(args.into_iter(), known, 0).and_then(|flags| {
+        let format = flags.format()?;
+        fun(&flags, format)
+    });
+    match r {
+        Err(ref e) if format == Format::Json => {
+            let errors = match *e {
+                CLIError::Install(InstallError::Many(ref errors)) => errors.iter().collect(),
+                CLIError::Install(ref e) => vec![e],
+                CLIError::IO(_) => {
+                    println!("{}", error_json(None, "io", &e.to_string()));
+                    return Ok(1);
+                }
+                _ => {
+                    println!("{}", error_json(None, "usage", &e.to_string()));
+                    return Ok(1);
+                }
+            };
+            // Each plugin which failed has its own error.
+            for e in errors {
+                let (kind, plugin) = match *e {
+                    InstallError::Parse(_) => ("parse", None),
+                    InstallError::Command(ref c) => ("command", Some(c.flavor.as_str())),
+                    InstallError::Missing(_) => ("missing", None),
+                    InstallError::IO(_) | InstallError::Many(_) => ("io", None),
+                };
+                println!("{}", error_json(plugin, kind, &e.to_string()));
+            }
+            Ok(1)
+        }
+        r => r,
+    }
+}
+
+/// Parses the value of `flag`, or the environment variable `var` if the flag is not given.
+fn setting<T, F>(flag: &Option<String>, name: &str, var: &str, parse: F) -> Result<Option<T>>
+where
+    F: Fn(&str) -> Option<T>,
+{
+    let (name, value) = match *flag {
+        Some(ref v) => (name, v.clone()),
+        None => {
+            match env::var(var) {
+                Ok(v) => (var, v),
+                Err(_) => return Ok(None),
+            }
+        }
+    };
+    match parse(&value) {
+        Some(v) => Ok(Some(v)),
+        None => Err(CLIError::InvalidValue(name.to_owned(), value)),
+    }
 }
 
 #[derive(Debug)]
@@ -123,6 +1427,14 @@ enum CLIError {
     NoCommand(String),
     NoTopic(String),
     NoFlag(String),
+    NoValue(String),
+    NoFlavorFile(String),
+    NotPackage(String),
+    NotRoot(String),
+    FileExists(String),
+    NoSpecs,
+    InvalidValue(String, String),
+    Config(String, ConfigError),
 }
 
 impl fmt::Display for CLIError {
@@ -142,6 +1454,29 @@ impl fmt::Display for CLIError {
             }
             CLIError::NoTopic(ref name) => write!(f, "no such help topic: {}", name),
             CLIError::NoFlag(ref name) => write!(f, "no such flag: {}", name),
+            CLIError::NoValue(ref name) => write!(f, "missing value for {}", name),
+            CLIError::NoFlavorFile(ref name) => {
+                write!(f, "{} not found in the current directory or its parents", name)
+            }
+            CLIError::NotPackage(ref path) => {
+                write!(f, "{}: not a package directory like <dir>/pack/<name>", path)
+            }
+            CLIError::NotRoot(ref path) => {
+                write!(
+                    f,
+                    "{}: not a plugin checkout; refusing to clean a directory which does not \
+                     look like a root of vim-flavor",
+                    path
+                )
+            }
+            CLIError::FileExists(ref path) => {
+                write!(f, "{}: already exists; use --overwrite to overwrite it", path)
+            }
+            CLIError::NoSpecs => write!(f, "no specs found"),
+            CLIError::InvalidValue(ref name, ref value) => {
+                write!(f, "invalid value for {}: {}", name, value)
+            }
+            CLIError::Config(ref path, ref e) => write!(f, "{}: {}", path, e),
         }
     }
 }
@@ -156,6 +1491,14 @@ impl Error for CLIError {
             CLIError::NoCommand(_) => "no such command",
             CLIError::NoTopic(_) => "no such help topic",
             CLIError::NoFlag(_) => "no such flag",
+            CLIError::NoValue(_) => "missing value",
+            CLIError::NoFlavorFile(_) => "flavor file not found",
+            CLIError::NotPackage(_) => "not a package directory",
+            CLIError::NotRoot(_) => "not a root",
+            CLIError::FileExists(_) => "file already exists",
+            CLIError::NoSpecs => "no specs found",
+            CLIError::InvalidValue(..) => "invalid value",
+            CLIError::Config(_, ref e) => e.description(),
         }
     }
 
@@ -168,6 +1511,14 @@ impl Error for CLIError {
             CLIError::NoCommand(_) => None,
             CLIError::NoTopic(_) => None,
             CLIError::NoFlag(_) => None,
+            CLIError::NoValue(_) => None,
+            CLIError::NoFlavorFile(_) => None,
+            CLIError::NotPackage(_) => None,
+            CLIError::NotRoot(_) => None,
+            CLIError::FileExists(_) => None,
+            CLIError::NoSpecs => None,
+            CLIError::InvalidValue(..) => None,
+            CLIError::Config(_, ref e) => e.cause(),
         }
     }
 }
@@ -71,6 +71,7 @@ impl<'a> Parser<'a> {
             "flavor" => Token::Flavor,
             "group" => Token::Group,
             "branch" => Token::Branch,
+            "opt" => Token::Opt,
             _ => Token::Ident(s.to_owned()),
         })
     }
@@ -114,12 +115,16 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_attrs(&mut self, vec: &mut Vec<Flavor>) -> Result<(), ParseError> {
-        self.parse_branch()?;
+        let attr = self.parse_attr()?;
         let mut f = vec.pop().ok_or(
             ParseError::Unexpected(Token::Comma, Token::Flavor),
         )?;
         self.parse_colon()?;
-        f.branch = self.parse_str()?;
+        match attr {
+            Token::Opt => f.opt = self.parse_bool()?,
+            Token::Group => f.group = self.parse_name()?,
+            _ => f.branch = self.parse_str()?,
+        }
         vec.push(f);
         Ok(())
     }
@@ -131,6 +136,28 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses a string or a symbol like `:development`.
+    fn parse_name(&mut self) -> Result<String, ParseError> {
+        match self.next_token()? {
+            Token::Str(s) => Ok(s),
+            Token::Colon => {
+                match self.next_token()? {
+                    Token::Ident(s) => Ok(s),
+                    _ => Err(ParseError::TypeMismatch),
+                }
+            }
+            _ => Err(ParseError::TypeMismatch),
+        }
+    }
+
+    fn parse_bool(&mut self) -> Result<bool, ParseError> {
+        match self.next_token()? {
+            Token::Ident(ref s) if s == "true" => Ok(true),
+            Token::Ident(ref s) if s == "false" => Ok(false),
+            _ => Err(ParseError::TypeMismatch),
+        }
+    }
+
     fn parse_colon(&mut self) -> Result<(), ParseError> {
         match self.next_token()? {
             Token::Colon => Ok(()),
@@ -138,9 +165,9 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_branch(&mut self) -> Result<(), ParseError> {
+    fn parse_attr(&mut self) -> Result<Token, ParseError> {
         match self.next_token()? {
-            Token::Branch => Ok(()),
+            t @ Token::Branch | t @ Token::Opt | t @ Token::Group => Ok(t),
             t => Err(ParseError::Unexpected(t, Token::Branch)),
         }
     }
@@ -157,15 +184,23 @@ pub enum Token {
     Flavor,
     Group,
     Branch,
+    Opt,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 /// Represents a plugin declaration.
 pub struct Flavor {
     /// A repository URI.
     pub repo: String,
     /// A branch for the plugin.
     pub branch: String,
+    /// Whether the plugin is optional, that is, loaded by `:packadd`.
+    pub opt: bool,
+    /// The group of the plugin: "runtime" (default), or "development" for plugins which
+    /// only `vim-flavor test` installs.
+    pub group: String,
+    /// The commit which the plugin is locked to by a lockfile.
+    pub commit: Option<String>,
 }
 
 impl Flavor {
@@ -174,6 +209,9 @@ impl Flavor {
         Flavor {
             repo: repo.to_owned(),
             branch: "master".to_owned(),
+            opt: false,
+            group: "runtime".to_owned(),
+            commit: None,
         }
     }
 }
@@ -330,8 +368,9 @@ mod tests {
         assert_eq!(p.next_token(), Ok(Token::Group));
         assert_eq!(p.offset, 13);
 
-        let mut p = Parser::new("branch");
+        let mut p = Parser::new("branch opt");
         assert_eq!(p.next_token(), Ok(Token::Branch));
+        assert_eq!(p.next_token(), Ok(Token::Opt));
     }
 
     #[test]
@@ -354,10 +393,44 @@ mod tests {
                 Flavor {
                     repo: "repo".to_owned(),
                     branch: "br".to_owned(),
+                    opt: false,
+                    group: "runtime".to_owned(),
+                    commit: None,
                 },
             ])
         );
 
+        let s = "flavor 'repo', opt: true, branch: 'br'";
+        let mut p = Parser::new(s);
+        assert_eq!(
+            p.parse(),
+            Ok(vec![
+                Flavor {
+                    repo: "repo".to_owned(),
+                    branch: "br".to_owned(),
+                    opt: true,
+                    group: "runtime".to_owned(),
+                    commit: None,
+                },
+            ])
+        );
+
+        let s = "flavor 'a', group: 'development'\n\
+                 flavor 'b', group: :development, branch: 'br'";
+        let mut p = Parser::new(s);
+        let fs = p.parse().unwrap();
+        assert_eq!(fs[0].group, "development");
+        assert_eq!(fs[1].group, "development");
+        assert_eq!(fs[1].branch, "br");
+
+        let s = "flavor 'repo', group: true";
+        let mut p = Parser::new(s);
+        assert_eq!(p.parse(), Err(ParseError::TypeMismatch));
+
+        let s = "flavor 'repo', opt: 'true'";
+        let mut p = Parser::new(s);
+        assert_eq!(p.parse(), Err(ParseError::TypeMismatch));
+
         let s = "flavor 'repo', branch 'missing a colon'";
         let mut p = Parser::new(s);
         assert!(p.parse().is_err());
@@ -0,0 +1,198 @@
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Event, Flavor, InstallError, Lock, Operation, Options, Root};
+use git;
+
+/// Saves the checked-out commits of `fs` under `root` as a snapshot, and returns its name.
+/// Returns None when no plugin is installed or nothing changed since the latest snapshot.
+///
+/// A snapshot is a file in `.snapshots` under the root, which is named after the time
+/// when it was taken, and consists of lines of a commit and a directory relative to the root.
+pub fn snapshot(fs: &[Flavor], root: &Root) -> io::Result<Option<String>> {
+    let mut content = String::new();
+    for f in fs {
+        let d = root.dir(f);
+        if !d.join(".git").exists() {
+            continue;
+        }
+        if let Some(commit) = git::read(&d, &["rev-parse", "HEAD"])? {
+            let rel = d.strip_prefix(root.path()).unwrap_or(&d);
+            content.push_str(&format!("{} {}\n", commit, rel.display()));
+        }
+    }
+    if content.is_empty() {
+        return Ok(None);
+    }
+    if let Some(latest) = snapshots(root)?.pop() {
+        if read(root, &latest)? == content {
+            return Ok(None);
+        }
+    }
+    let dir = snapshot_dir(root);
+    fs::create_dir_all(&dir)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let mut name = timestamp(now);
+    let mut n = 0;
+    while dir.join(&name).exists() {
+        n += 1;
+        name = format!("{}-{}", timestamp(now), n);
+    }
+    fs::File::create(dir.join(&name))?.write_all(content.as_bytes())?;
+    Ok(Some(name))
+}
+
+/// Returns the names of snapshots under `root`, oldest first.
+pub fn snapshots(root: &Root) -> io::Result<Vec<String>> {
+    let dir = snapshot_dir(root);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        if let Ok(name) = entry?.file_name().into_string() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Restores every checkout under `root` to the commit recorded in the snapshot `name`,
+/// or the latest snapshot if `name` is None. Returns the name of the restored snapshot.
+/// A checkout which is not installed is skipped, which is sent to `opts.observer`.
+/// The root is locked while rolling back.
+pub fn rollback(root: &Root, name: Option<&str>, opts: &Options) -> Result<String, InstallError> {
+    let _lock = Lock::acquire(root, opts)?;
+    let mut names = snapshots(root)?;
+    let name = match name {
+        Some(name) => {
+            if !names.iter().any(|n| n == name) {
+                let msg = format!("no such snapshot: {}", name);
+                return Err(io::Error::new(io::ErrorKind::NotFound, msg).into());
+            }
+            name.to_owned()
+        }
+        None => {
+            names.pop().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no snapshots found")
+            })?
+        }
+    };
+    for (commit, rel) in parse(&read(root, &name)?) {
+        let d = root.path().join(&rel);
+        if !d.join(".git").exists() {
+            opts.notify(&rel.to_string_lossy(), &Event::Skipped);
+            continue;
+        }
+        let name = rel.to_string_lossy();
+        let object = format!("{}^{{commit}}", commit);
+        if git::read(&d, &["cat-file", "-e", &object])?.is_none() {
+            git::exec(&name, Operation::Fetch, &d, &["fetch", "--depth", "1", "origin", commit])?;
+        }
+        git::exec(&name, Operation::Checkout, &d, &["reset", "--quiet", "--hard", commit])?;
+    }
+    Ok(name)
+}
+
+fn snapshot_dir(root: &Root) -> PathBuf {
+    root.path().join(".snapshots")
+}
+
+fn read(root: &Root, name: &str) -> io::Result<String> {
+    let mut content = String::new();
+    fs::File::open(snapshot_dir(root).join(name))?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+fn parse(content: &str) -> Vec<(&str, PathBuf)> {
+    content
+        .lines()
+        .filter_map(|l| {
+            let mut l = l.splitn(2, ' ');
+            Some((l.next()?, PathBuf::from(l.next()?)))
+        })
+        .collect()
+}
+
+/// Formats seconds since the Unix epoch as `YYYYmmddTHHMMSSZ`.
+fn timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        y,
+        m,
+        d,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Layout, Options, install, update};
+    use testing;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0), "19700101T000000Z");
+        assert_eq!(timestamp(951782400), "20000229T000000Z");
+        assert_eq!(timestamp(1508284800 + 3723), "20171018T010203Z");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("abc start/a\ndef opt/b\n"),
+            vec![("abc", PathBuf::from("start/a")), ("def", PathBuf::from("opt/b"))]
+        );
+    }
+
+    #[test]
+    fn test_rollback() {
+        let dir = testing::temp_dir("rust-vim-flavor-rollback-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let flavors = [Flavor::new(repo.to_str().unwrap())];
+
+        assert_eq!(snapshot(&flavors, &root).unwrap(), None);
+        assert!(rollback(&root, None, &Options::default()).is_err());
+
+        install(&flavors, &root, &Options::default()).unwrap();
+        let before = testing::git(&repo, &["rev-parse", "HEAD"]);
+        testing::commit(&repo, "plugin/a.vim", "Second commit");
+        update(&flavors, &root, &Options::default()).unwrap();
+        let names = snapshots(&root).unwrap();
+        assert_eq!(names.len(), 1);
+        assert!(snapshot(&flavors, &root).unwrap().is_some());
+        assert_eq!(snapshots(&root).unwrap().len(), 2);
+
+        // Names are taken only from the snapshots directory.
+        let outside = rollback(&root, Some("../.snapshots/x"), &Options::default());
+        let r = rollback(&root, Some(&names[0]), &Options::default());
+        let head = testing::git(&root.dir(&flavors[0]), &["rev-parse", "HEAD"]);
+        if let Some(e) = fs::remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert!(outside.is_err());
+        assert_eq!(r.unwrap(), names[0]);
+        assert_eq!(head, before);
+    }
+}
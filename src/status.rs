@@ -0,0 +1,218 @@
+use std::io;
+
+use super::{CommandError, Flavor, InstallError, Operation, Options, Root};
+use git;
+
+#[derive(Debug, PartialEq)]
+/// Represents the state of an installed plugin.
+pub struct Status {
+    /// The checked-out branch or tag, if any.
+    pub branch: Option<String>,
+    /// The abbreviated name of the checked-out commit.
+    /// Empty if the directory is not a git repository.
+    pub commit: String,
+    /// The date of the checked-out commit.
+    pub date: String,
+    /// Whether tracked files are modified.
+    pub dirty: bool,
+    /// The URL of the remote "origin".
+    pub url: Option<String>,
+    /// Whether `url` is the one which the flavor resolves to.
+    pub url_matches: bool,
+}
+
+/// Returns the state of `f` installed under `root`, or None if it is not installed yet.
+pub fn status(f: &Flavor, root: &Root, opts: &Options) -> io::Result<Option<Status>> {
+    let dir = root.dir(f);
+    if !dir.exists() {
+        return Ok(None);
+    }
+    if !dir.join(".git").exists() {
+        return Ok(Some(Status {
+            branch: None,
+            commit: String::new(),
+            date: String::new(),
+            dirty: false,
+            url: None,
+            url_matches: false,
+        }));
+    }
+    let branch = match git::read(&dir, &["symbolic-ref", "--short", "-q", "HEAD"])? {
+        Some(b) => Some(b),
+        None => git::read(&dir, &["describe", "--tags", "--exact-match"])?,
+    };
+    let log = git::read(&dir, &["log", "-1", "--format=%h %cd", "--date=short"])?
+        .unwrap_or_default();
+    let mut log = log.splitn(2, ' ');
+    let commit = log.next().unwrap_or_default().to_owned();
+    let date = log.next().unwrap_or_default().to_owned();
+    let dirty = git::read(&dir, &["status", "--porcelain", "--untracked-files=no"])?
+        .map_or(false, |s| !s.is_empty());
+    let url = git::read(&dir, &["config", "--get", "remote.origin.url"])?;
+    let url_matches = url.as_ref() == Some(&super::url(&f.repo, opts));
+    Ok(Some(Status {
+        branch,
+        commit,
+        date,
+        dirty,
+        url,
+        url_matches,
+    }))
+}
+
+#[derive(Debug, PartialEq)]
+/// Represents a plugin whose checked-out commit differs from the one on its remote.
+pub struct Outdated {
+    /// The checked-out commit.
+    pub current: String,
+    /// The commit which the branch points to on the remote, or None if it no longer exists.
+    pub available: Option<String>,
+}
+
+/// Compares the checked-out commit of `f` with the URL which `f` resolves to, as `install`
+/// does, without touching the working tree. The remote of the checkout is not used, since it
+/// is stale if the repository has changed in the flavor file.
+/// Returns None if `f` is up to date or not installed yet.
+pub fn outdated(f: &Flavor, root: &Root, opts: &Options) -> Result<Option<Outdated>, InstallError> {
+    let dir = root.dir(f);
+    if !dir.join(".git").exists() {
+        return Ok(None);
+    }
+    let current = git::read(&dir, &["rev-parse", "HEAD"])?.unwrap_or_default();
+    let url = super::url(&f.repo, opts);
+    let args = ["ls-remote", url.as_str(), f.branch.as_str()];
+    let output = git::run(&dir, &args)?;
+    if !output.status.success() {
+        return Err(CommandError::new(&f.repo, Operation::Fetch, "git", &args, &output).into());
+    }
+    let available = remote_commit(&String::from_utf8_lossy(&output.stdout), &f.branch);
+    if available.as_ref() == Some(&current) {
+        return Ok(None);
+    }
+    Ok(Some(Outdated { current, available }))
+}
+
+/// Finds the commit which `branch` points to in the output of `git ls-remote`.
+/// Branches take precedence over tags, and annotated tags are peeled.
+fn remote_commit(listing: &str, branch: &str) -> Option<String> {
+    let refs: Vec<(&str, &str)> = listing
+        .lines()
+        .filter_map(|l| {
+            let mut l = l.split('\t');
+            Some((l.next()?, l.next()?))
+        })
+        .collect();
+    let names = [
+        format!("refs/heads/{}", branch),
+        format!("refs/tags/{}^{{}}", branch),
+        format!("refs/tags/{}", branch),
+    ];
+    names
+        .iter()
+        .filter_map(|n| refs.iter().find(|&&(_, r)| r == n))
+        .map(|&(c, _)| c.to_owned())
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Layout, Options, install};
+    use testing;
+
+    use std::fs;
+
+    #[test]
+    fn test_status() {
+        let dir = testing::temp_dir("rust-vim-flavor-status-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let opts = Options::default();
+        let f = Flavor::new(repo.to_str().unwrap());
+
+        assert_eq!(status(&f, &root, &opts).unwrap(), None);
+
+        let flavors = [Flavor::new(repo.to_str().unwrap())];
+        install(&flavors, &root, &opts).unwrap();
+        let s = status(&f, &root, &opts).unwrap().unwrap();
+        assert_eq!(s.branch, Some("master".to_owned()));
+        assert_eq!(s.commit, testing::git(&repo, &["rev-parse", "--short", "HEAD"]));
+        assert!(!s.dirty);
+        assert!(s.url_matches);
+
+        fs::write(root.dir(&f).join("plugin/a.vim"), "modified").unwrap();
+        let s = status(&f, &root, &opts).unwrap().unwrap();
+        assert!(s.dirty);
+
+        testing::git(&repo, &["tag", "v1.0.0"]);
+        let root = Root::new(dir.join("tagged"), Layout::Flat);
+        let tagged = Flavor {
+            branch: "v1.0.0".to_owned(),
+            ..Flavor::new(repo.to_str().unwrap())
+        };
+        install(&[tagged], &root, &opts).unwrap();
+        let s = status(&f, &root, &opts).unwrap().unwrap();
+        assert_eq!(s.branch, Some("v1.0.0".to_owned()));
+
+        if let Some(e) = fs::remove_dir_all(dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+    }
+
+    #[test]
+    fn test_outdated() {
+        let dir = testing::temp_dir("rust-vim-flavor-outdated-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let root = Root::new(dir.join("root"), Layout::Flat);
+        let opts = Options::default();
+        let f = Flavor::new(repo.to_str().unwrap());
+
+        assert_eq!(outdated(&f, &root, &opts).unwrap(), None);
+
+        let current = testing::git(&repo, &["rev-parse", "HEAD"]);
+        let flavors = [Flavor::new(repo.to_str().unwrap())];
+        install(&flavors, &root, &opts).unwrap();
+        assert_eq!(outdated(&f, &root, &opts).unwrap(), None);
+
+        let available = testing::commit(&repo, "plugin/a.vim", "Second commit");
+        let r = outdated(&f, &root, &opts);
+        let head = testing::git(&root.dir(&f), &["rev-parse", "HEAD"]);
+
+        // The URL is resolved by rewrites, not taken from the remote of the checkout.
+        let fork = dir.join("fork");
+        testing::git(&dir, &["clone", "-q", repo.to_str().unwrap(), fork.to_str().unwrap()]);
+        let forked = testing::commit(&fork, "plugin/a.vim", "Forked commit");
+        let rewritten = Options {
+            rewrites: vec![(repo.to_str().unwrap().to_owned(), fork.to_str().unwrap().to_owned())],
+            ..Options::default()
+        };
+        let r2 = outdated(&f, &root, &rewritten);
+        if let Some(e) = fs::remove_dir_all(dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        assert_eq!(
+            r.unwrap(),
+            Some(Outdated {
+                current: current.clone(),
+                available: Some(available),
+            })
+        );
+        assert_eq!(head, current);
+        assert_eq!(r2.unwrap().and_then(|o| o.available), Some(forked));
+    }
+
+    #[test]
+    fn test_remote_commit() {
+        let listing = "1111\tHEAD\n\
+                       2222\trefs/heads/master\n\
+                       3333\trefs/tags/v1.0\n\
+                       4444\trefs/tags/v1.0^{}\n\
+                       5555\trefs/tags/v1.1\n";
+        assert_eq!(remote_commit(listing, "master"), Some("2222".to_owned()));
+        assert_eq!(remote_commit(listing, "v1.0"), Some("4444".to_owned()));
+        assert_eq!(remote_commit(listing, "v1.1"), Some("5555".to_owned()));
+        assert_eq!(remote_commit(listing, "dev"), None);
+    }
+}
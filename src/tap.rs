@@ -0,0 +1,324 @@
+#[derive(Debug, Clone, PartialEq)]
+/// The outcome of a test.
+pub enum Outcome {
+    /// Passed, that is, "ok".
+    Passed,
+    /// Failed, that is, "not ok".
+    Failed,
+    /// Skipped by a SKIP or TODO directive, with its reason.
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A test line of a TAP stream.
+pub struct TestCase {
+    /// The description of the test without the number and the directive, or "#N" for the
+    /// N-th test without a description.
+    pub name: String,
+    /// Whether the test passed, failed or was skipped.
+    pub outcome: Outcome,
+    /// Comment lines which follow the test, without the leading '#'.
+    pub diagnostics: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The result of a spec file, which is parsed from its TAP stream.
+pub struct Spec {
+    /// The name of the spec, usually its path.
+    pub name: String,
+    /// The tests in the order of the stream.
+    pub tests: Vec<TestCase>,
+    /// Why the spec failed as a whole: a bail-out, a wrong number of tests, or a crash.
+    pub error: Option<String>,
+}
+
+impl Spec {
+    /// Counts the tests which passed, failed and were skipped.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for t in &self.tests {
+            match t.outcome {
+                Outcome::Passed => counts.0 += 1,
+                Outcome::Failed => counts.1 += 1,
+                Outcome::Skipped(_) => counts.2 += 1,
+            }
+        }
+        counts
+    }
+
+    /// Reports whether any test failed or the spec failed as a whole.
+    pub fn failed(&self) -> bool {
+        self.error.is_some() || self.tests.iter().any(|t| t.outcome == Outcome::Failed)
+    }
+}
+
+/// Parses `output`, a TAP stream of the spec `name`.
+/// Lines other than a plan, tests, comments and a bail-out are ignored.
+pub fn parse_tap(name: &str, output: &str) -> Spec {
+    let mut spec = Spec {
+        name: name.to_owned(),
+        tests: Vec::new(),
+        error: None,
+    };
+    let mut plan: Option<usize> = None;
+    for l in output.lines() {
+        let l = l.trim();
+        if l.starts_with("1..") {
+            plan = l[3..].split_whitespace().next().and_then(|n| n.parse().ok());
+        } else if let Some((ok, rest)) = test_line(l) {
+            let mut t = parse_test(ok, rest);
+            if t.name.is_empty() {
+                t.name = format!("#{}", spec.tests.len() + 1);
+            }
+            spec.tests.push(t);
+        } else if l.starts_with('#') {
+            if let Some(t) = spec.tests.last_mut() {
+                t.diagnostics.push(l[1..].trim().to_owned());
+            }
+        } else if l.starts_with("Bail out!") {
+            spec.error = Some(l.to_owned());
+            return spec;
+        }
+    }
+    spec.error = match plan {
+        Some(n) if n != spec.tests.len() => {
+            Some(format!("planned {} tests but ran {}", n, spec.tests.len()))
+        }
+        None => Some("no plan".to_owned()),
+        _ => None,
+    };
+    spec
+}
+
+/// Splits a test line into whether it is "ok" and the rest. "ok" and "not ok" must be followed
+/// by white space or the end of the line, so that a line such as "okay" is not a test.
+fn test_line(l: &str) -> Option<(bool, &str)> {
+    for &(keyword, ok) in &[("ok", true), ("not ok", false)] {
+        if l.starts_with(keyword) {
+            let rest = &l[keyword.len()..];
+            if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                return Some((ok, rest));
+            }
+        }
+    }
+    None
+}
+
+fn parse_test(ok: bool, rest: &str) -> TestCase {
+    let rest = rest.trim();
+    let number = rest.find(|c: char| !c.is_digit(10)).unwrap_or_else(|| rest.len());
+    let rest = rest[number..].trim();
+    let rest = if rest.starts_with("- ") { &rest[2..] } else { rest };
+    let (name, directive) = match rest.find('#') {
+        Some(i) => (&rest[..i], rest[i + 1..].trim()),
+        None => (rest, ""),
+    };
+    let keyword = directive.get(..4).unwrap_or("");
+    let skipped = keyword.eq_ignore_ascii_case("SKIP") || keyword.eq_ignore_ascii_case("TODO");
+    let outcome = if skipped {
+        Outcome::Skipped(directive[4..].trim().to_owned())
+    } else if ok {
+        Outcome::Passed
+    } else {
+        Outcome::Failed
+    };
+    TestCase {
+        name: name.trim().to_owned(),
+        outcome,
+        diagnostics: Vec::new(),
+    }
+}
+
+/// Reports whether `name` contains a match of `pattern`, in which '*' matches any string and
+/// '?' matches any character.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    fn at(p: &[char], n: &[char]) -> bool {
+        match p.split_first() {
+            None => true,
+            Some((&'*', rest)) => (0..n.len() + 1).any(|i| at(rest, &n[i..])),
+            Some((&c, rest)) => {
+                match n.split_first() {
+                    Some((&d, n)) if c == '?' || c == d => at(rest, n),
+                    _ => false,
+                }
+            }
+        }
+    }
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    (0..n.len() + 1).any(|i| at(&p, &n[i..]))
+}
+
+/// Returns a JUnit XML report of `specs`, each of which is a test suite.
+pub fn junit(specs: &[Spec]) -> String {
+    let mut total = (0, 0, 0, 0);
+    let mut suites = String::new();
+    for s in specs {
+        let (passed, failed, skipped) = s.counts();
+        let errors = if s.error.is_some() { 1 } else { 0 };
+        let tests = passed + failed + skipped + errors;
+        total = (total.0 + tests, total.1 + failed, total.2 + skipped, total.3 + errors);
+        suites.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" \
+             errors=\"{}\">\n",
+            escape(&s.name),
+            tests,
+            failed,
+            skipped,
+            errors
+        ));
+        for t in &s.tests {
+            let case = format!(
+                "    <testcase name=\"{}\" classname=\"{}\"",
+                escape(&t.name),
+                escape(&s.name)
+            );
+            match t.outcome {
+                Outcome::Passed => suites.push_str(&format!("{}/>\n", case)),
+                Outcome::Failed => {
+                    suites.push_str(&format!(
+                        "{}>\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                        case,
+                        escape(&t.name),
+                        escape(&t.diagnostics.join("\n"))
+                    ))
+                }
+                Outcome::Skipped(ref reason) => {
+                    suites.push_str(&format!(
+                        "{}>\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                        case,
+                        escape(reason)
+                    ))
+                }
+            }
+        }
+        if let Some(ref e) = s.error {
+            suites.push_str(&format!(
+                "    <testcase name=\"{0}\" classname=\"{0}\">\n      \
+                 <error message=\"{1}\"/>\n    </testcase>\n",
+                escape(&s.name),
+                escape(e)
+            ));
+        }
+        suites.push_str("  </testsuite>\n");
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuites tests=\"{}\" failures=\"{}\" skipped=\"{}\" errors=\"{}\">\n\
+         {}</testsuites>\n",
+        total.0,
+        total.1,
+        total.2,
+        total.3,
+        suites
+    )
+}
+
+fn escape(s: &str) -> String {
+    let mut e = String::new();
+    for ch in s.chars() {
+        match ch {
+            '&' => e.push_str("&amp;"),
+            '<' => e.push_str("&lt;"),
+            '>' => e.push_str("&gt;"),
+            '"' => e.push_str("&quot;"),
+            ch if ch < ' ' && ch != '\n' && ch != '\t' => (),
+            ch => e.push(ch),
+        }
+    }
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tap() {
+        let s = parse_tap(
+            "t/a.vim",
+            "1..5\n\
+             ok 1 - a works 2\n\
+             not ok 2 - b <works>\n\
+             # Expected 1 == 2\n\
+             #     Actual value: 1\n\
+             ok 3 - c # SKIP not yet\n\
+             not ok 4 - d # TODO\n\
+             ok 5\n",
+        );
+        assert_eq!(s.counts(), (2, 1, 2));
+        assert!(s.failed());
+        assert_eq!(s.error, None);
+        assert_eq!(s.tests[0].name, "a works 2");
+        assert_eq!(
+            s.tests[1].diagnostics,
+            vec!["Expected 1 == 2".to_owned(), "Actual value: 1".to_owned()]
+        );
+        assert_eq!(s.tests[2].outcome, Outcome::Skipped("not yet".to_owned()));
+        assert_eq!(s.tests[3].outcome, Outcome::Skipped("".to_owned()));
+        assert_eq!(s.tests[4].name, "#5");
+
+        let s = parse_tap("t/b.vim", "1..2\nok 1 - a\n");
+        assert_eq!(s.error, Some("planned 2 tests but ran 1".to_owned()));
+        let s = parse_tap("t/b.vim", "ok 1 - a\nBail out! no vim\nok 2\n");
+        assert_eq!(s.error, Some("Bail out! no vim".to_owned()));
+        assert_eq!(s.tests.len(), 1);
+        let s = parse_tap("t/b.vim", "ok 1 - a\n");
+        assert_eq!(s.error, Some("no plan".to_owned()));
+        assert!(!parse_tap("t/c.vim", "ok 1\n1..1\n").failed());
+
+        // Words which merely start with "ok" are not tests.
+        let s = parse_tap("t/d.vim", "okay, vim started\nnot okay\n1..2\nok\nnot ok\tb\n");
+        assert_eq!(s.error, None);
+        assert_eq!(s.tests[0].name, "#1");
+        assert_eq!(s.tests[1].outcome, Outcome::Failed);
+        assert_eq!(s.tests[1].name, "b");
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("b", "abc"));
+        assert!(matches("a*c", "xabbcx"));
+        assert!(matches("*", ""));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(matches("", "abc"));
+        assert!(matches("", ""));
+        assert!(!matches("abd", "abc"));
+        assert!(matches("日?", "日本語"));
+    }
+
+    #[test]
+    fn test_junit() {
+        let specs = [
+            parse_tap("t/a.vim", "1..2\nok 1 - a\nnot ok 2 - \"b\"\n# x < y\n"),
+            parse_tap("t/b.vim", "1..1\nok 1 - c # SKIP later\n"),
+            parse_tap("t/c.vim", ""),
+        ];
+        assert_eq!(
+            junit(&specs),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <testsuites tests=\"4\" failures=\"1\" skipped=\"1\" errors=\"1\">\n\
+             \x20 <testsuite name=\"t/a.vim\" tests=\"2\" failures=\"1\" skipped=\"0\" \
+             errors=\"0\">\n\
+             \x20   <testcase name=\"a\" classname=\"t/a.vim\"/>\n\
+             \x20   <testcase name=\"&quot;b&quot;\" classname=\"t/a.vim\">\n\
+             \x20     <failure message=\"&quot;b&quot;\">x &lt; y</failure>\n\
+             \x20   </testcase>\n\
+             \x20 </testsuite>\n\
+             \x20 <testsuite name=\"t/b.vim\" tests=\"1\" failures=\"0\" skipped=\"1\" \
+             errors=\"0\">\n\
+             \x20   <testcase name=\"c\" classname=\"t/b.vim\">\n\
+             \x20     <skipped message=\"later\"/>\n\
+             \x20   </testcase>\n\
+             \x20 </testsuite>\n\
+             \x20 <testsuite name=\"t/c.vim\" tests=\"1\" failures=\"0\" skipped=\"0\" \
+             errors=\"1\">\n\
+             \x20   <testcase name=\"t/c.vim\" classname=\"t/c.vim\">\n\
+             \x20     <error message=\"no plan\"/>\n\
+             \x20   </testcase>\n\
+             \x20 </testsuite>\n\
+             </testsuites>\n"
+        );
+    }
+}
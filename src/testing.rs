@@ -0,0 +1,54 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Creates an empty directory named `name` under the temporary directory.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let mut dir = env::temp_dir();
+    dir.push(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs git in `dir` with a fixed identity, and returns its trimmed standard output.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .env("GIT_AUTHOR_NAME", "vim-flavor")
+        .env("GIT_AUTHOR_EMAIL", "vim-flavor@example.com")
+        .env("GIT_COMMITTER_NAME", "vim-flavor")
+        .env("GIT_COMMITTER_EMAIL", "vim-flavor@example.com")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?}: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_owned()
+}
+
+/// Creates a repository at `dir` with a commit on the branch "master".
+pub fn repo(dir: &Path) {
+    fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "-q"]);
+    git(dir, &["symbolic-ref", "HEAD", "refs/heads/master"]);
+    commit(dir, "plugin/a.vim", "Initial commit");
+}
+
+/// Writes `file` in the repository at `dir` and commits it with `msg`.
+/// Returns the new commit.
+pub fn commit(dir: &Path, file: &str, msg: &str) -> String {
+    let path = dir.join(file);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, msg).unwrap();
+    git(dir, &["add", "-A"]);
+    git(dir, &["commit", "-q", "-m", msg]);
+    git(dir, &["rev-parse", "HEAD"])
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::Path;
+
+use super::{Flavor, InstallError, Layout, Operation, Options, Root, complete, install, temp_dir};
+use git;
+use helptags;
+
+/// The file in a vendor directory which records where each plugin comes from.
+const MANIFEST: &str = "vim-flavor.manifest";
+
+/// The root under a vendor directory into which plugins are installed before being copied.
+const STAGING: &str = ".tmp-vendor";
+
+/// Copies the tree of each flavor in `fs` at its resolved commit into `dir`, without `.git`,
+/// and writes a manifest of lines of a commit, a URL and a directory name.
+/// The flavors are resolved as `install` does, that is, locked ones to their commits and
+/// others to their branches or tags. Plugins which were vendored but are no longer in `fs`
+/// are removed.
+pub fn vendor(fs: &[Flavor], dir: &Path, opts: &Options) -> Result<(), InstallError> {
+    let staging = Root::new(dir.join(STAGING), Layout::Flat);
+    if staging.path().exists() {
+        fs::remove_dir_all(staging.path())?;
+    }
+    let r = install(fs, &staging, opts).and_then(|_| copy(fs, &staging, dir, opts));
+    fs::remove_dir_all(staging.path())?;
+    let manifest = r?;
+    if let Ok(old) = fs::read_to_string(dir.join(MANIFEST)) {
+        for name in old.lines().filter_map(|l| l.splitn(3, ' ').nth(2)) {
+            let stale = !manifest.lines().any(|l| l.splitn(3, ' ').nth(2) == Some(name));
+            let d = dir.join(name);
+            if stale && !name.starts_with('.') && !name.contains('/') && d.is_dir() {
+                fs::remove_dir_all(d)?;
+            }
+        }
+    }
+    fs::write(dir.join(MANIFEST), manifest)?;
+    Ok(())
+}
+
+/// Copies the checkouts of `fs` under `staging` into `dir`, and returns the manifest.
+fn copy(
+    fs: &[Flavor],
+    staging: &Root,
+    dir: &Path,
+    opts: &Options,
+) -> Result<String, InstallError> {
+    let mut manifest = String::new();
+    for f in fs {
+        let src = staging.dir(f);
+        let name = src.file_name().expect("plugin directory has no name");
+        let d = dir.join(name);
+        let tmp = temp_dir(&d);
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        let work_tree = format!("--work-tree={}", tmp.display());
+        fs::create_dir_all(&tmp)?;
+        // Resetting with another work tree writes every file of HEAD there.
+        let args = [work_tree.as_str(), "reset", "--quiet", "--hard"];
+        if let Err(e) = git::exec(&f.repo, Operation::Checkout, &src, &args) {
+            fs::remove_dir_all(&tmp)?;
+            return Err(e);
+        }
+        let doc = tmp.join("doc");
+        if doc.is_dir() {
+            helptags(&doc)?;
+        }
+        if d.exists() {
+            fs::remove_dir_all(&d)?;
+        }
+        fs::rename(&tmp, &d)?;
+        let commit = git::read(&src, &["rev-parse", "HEAD"])?.unwrap_or_default();
+        manifest.push_str(&format!(
+            "{} {} {}\n",
+            commit,
+            complete(&f.repo, opts),
+            name.to_string_lossy()
+        ));
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing;
+
+    #[test]
+    fn test_vendor() {
+        let dir = testing::temp_dir("rust-vim-flavor-vendor-test");
+        let repo = dir.join("plugin");
+        testing::repo(&repo);
+        let first = testing::git(&repo, &["rev-parse", "HEAD"]);
+        testing::commit(&repo, "doc/a.txt", "*a*");
+        let url = repo.to_str().unwrap();
+        let vendored = dir.join("vendor");
+        fs::create_dir_all(vendored.join("old")).unwrap();
+        fs::write(vendored.join(MANIFEST), "0 x old\n").unwrap();
+
+        let mut flavors = [Flavor::new(url)];
+        let r1 = vendor(&flavors, &vendored, &Options::default());
+        let name = Root::new(&vendored, Layout::Flat).dir(&flavors[0]);
+        let tags = name.join("doc").join("tags").exists();
+        flavors[0].commit = Some(first.clone());
+        let r2 = vendor(&flavors, &vendored, &Options::default());
+        let manifest = fs::read_to_string(vendored.join(MANIFEST)).unwrap();
+        let mut entries: Vec<String> = fs::read_dir(&vendored)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        let plugin = name.join("plugin").join("a.vim").exists();
+        let doc = name.join("doc").exists();
+        let git = name.join(".git").exists();
+        if let Some(e) = fs::remove_dir_all(&dir).err() {
+            eprintln!("cannot remove a temporary directory: {}", e);
+        }
+        let n = name.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(r1.is_ok());
+        assert!(tags);
+        assert!(r2.is_ok());
+        assert_eq!(manifest, format!("{} {} {}\n", first, url, n));
+        assert_eq!(entries, vec![n, MANIFEST.to_owned()]);
+        assert!(plugin);
+        assert!(!doc);
+        assert!(!git);
+    }
+}